@@ -0,0 +1,90 @@
+use malachite::Natural;
+use malachite::base::num::basic::traits::{One, Zero};
+
+use crate::utils::log10_natural;
+
+/// A count computed by a model counter. Counters give the exact count of unweighted formulas,
+/// which is kept with arbitrary precision, and only its log10 for weighted formulas or when they
+/// print an estimate.
+#[derive(Clone, Debug)]
+pub struct Count {
+    exact: Option<Natural>,
+    log10: f64,
+}
+
+impl Count {
+
+    /// The exact count
+    pub fn exact(count: Natural) -> Self {
+        let log10 = log10_natural(&count);
+        Self {
+            exact: Some(count),
+            log10,
+        }
+    }
+
+    /// A count of which only the log10 is known
+    pub fn from_log10(log10: f64) -> Self {
+        Self {
+            exact: None,
+            log10,
+        }
+    }
+
+    /// The count of an unsatisfiable problem
+    pub fn zero() -> Self {
+        Self::exact(Natural::ZERO)
+    }
+
+    /// The count of a problem with a single model
+    pub fn one() -> Self {
+        Self::exact(Natural::ONE)
+    }
+
+    /// Returns the exact count, if known
+    pub fn exact_value(&self) -> Option<&Natural> {
+        self.exact.as_ref()
+    }
+
+    /// Returns the log10 of the count
+    pub fn log10(&self) -> f64 {
+        self.log10
+    }
+
+    /// Returns the product of the two counts, exact if both are exact
+    pub fn multiply(&self, other: &Count) -> Self {
+        match (self.exact.as_ref(), other.exact.as_ref()) {
+            (Some(a), Some(b)) => Self::exact(a * b),
+            _ => Self::from_log10(self.log10 + other.log10),
+        }
+    }
+}
+
+impl std::fmt::Display for Count {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.exact.as_ref() {
+            Some(count) => write!(f, "{} (log10 {})", count, self.log10),
+            None => write!(f, "10^{}", self.log10),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn products_stay_exact_beyond_floating_point_precision() {
+        // 2^70 + 1 can not be represented as a f64
+        let large = Count::exact((Natural::ONE << 70u64) + Natural::ONE);
+        let product = large.multiply(&Count::exact(Natural::from(3u32)));
+        assert_eq!(product.exact_value(), Some(&((Natural::ONE << 70u64) * Natural::from(3u32) + Natural::from(3u32))));
+        assert!((product.log10() - (70.0 * 2f64.log10() + 3f64.log10())).abs() < 1e-9);
+        let estimate = product.multiply(&Count::from_log10(2.0));
+        assert_eq!(estimate.exact_value(), None);
+        assert!((estimate.log10() - product.log10() - 2.0).abs() < 1e-9);
+        assert_eq!(Count::zero().log10(), f64::NEG_INFINITY);
+        assert!(Count::exact(Natural::from(12u32)).to_string().starts_with("12 (log10 1.079"));
+        assert_eq!(Count::from_log10(2.5).to_string(), "10^2.5");
+    }
+}
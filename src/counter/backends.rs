@@ -0,0 +1,146 @@
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::tools;
+use super::{ModelCounter, RestrictedFormula};
+
+/// Returns the file as standard input of a counter, or no input if it can not be opened
+fn stdin_from(input: &Path) -> Stdio {
+    match File::open(input) {
+        Ok(file) => Stdio::from(file),
+        Err(_) => Stdio::null(),
+    }
+}
+
+/// The d4 knowledge compiler, reading the formula on its standard input. It is launched in its
+/// model counting mode, which counts all the models of the formula.
+pub struct D4;
+
+impl ModelCounter for D4 {
+
+    fn name(&self) -> &str {
+        "d4"
+    }
+
+    fn executable(&self) -> &str {
+        "d4"
+    }
+
+    fn launch(&self, _formula: &RestrictedFormula, input: &Path, _timeout: u64) -> Command {
+        let mut command = tools::command(self.executable());
+        command.stdin(stdin_from(input));
+        command
+    }
+}
+
+/// The ganak model counter, reading the formula on its standard input. Weighted counts are
+/// computed in mode 1.
+pub struct Ganak;
+
+impl ModelCounter for Ganak {
+
+    fn name(&self) -> &str {
+        "ganak"
+    }
+
+    fn executable(&self) -> &str {
+        "ganak"
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn supports_projection(&self) -> bool {
+        true
+    }
+
+    fn launch(&self, formula: &RestrictedFormula, input: &Path, _timeout: u64) -> Command {
+        let mut command = tools::command(self.executable());
+        if formula.is_weighted() {
+            command.args(["--mode", "1"]);
+        }
+        command.stdin(stdin_from(input));
+        command
+    }
+}
+
+/// The sharpSAT-TD model counter, with the options used in the model counting competition. It
+/// counts all the models of the formula.
+pub struct SharpsatTd;
+
+impl ModelCounter for SharpsatTd {
+
+    fn name(&self) -> &str {
+        "sharpSAT-TD"
+    }
+
+    fn executable(&self) -> &str {
+        "sharpSAT"
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn launch(&self, formula: &RestrictedFormula, input: &Path, _timeout: u64) -> Command {
+        let mut command = tools::command(self.executable());
+        command.args(["-decot", "1", "-decow", "100", "-cs", "3500", "-tmpdir"]).arg(std::env::temp_dir());
+        if formula.is_weighted() {
+            command.arg("-WE");
+        }
+        command.arg(input);
+        command
+    }
+}
+
+/// The GPMC model counter. The mode selects (projected) (weighted) model counting.
+pub struct Gpmc;
+
+impl ModelCounter for Gpmc {
+
+    fn name(&self) -> &str {
+        "GPMC"
+    }
+
+    fn executable(&self) -> &str {
+        "gpmc"
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn supports_projection(&self) -> bool {
+        true
+    }
+
+    fn launch(&self, formula: &RestrictedFormula, input: &Path, _timeout: u64) -> Command {
+        // Mode 2 is projected model counting and mode 3 projected weighted model counting
+        let mode = if formula.is_weighted() { 3 } else { 2 };
+        let mut command = tools::command(self.executable());
+        command.arg(format!("-mode={}", mode)).arg(input);
+        command
+    }
+}
+
+/// The ExactMC model counter, in competition mode. It counts all the models of the formula.
+pub struct ExactMc;
+
+impl ModelCounter for ExactMc {
+
+    fn name(&self) -> &str {
+        "ExactMC"
+    }
+
+    fn executable(&self) -> &str {
+        "ExactMC"
+    }
+
+    fn launch(&self, _formula: &RestrictedFormula, input: &Path, _timeout: u64) -> Command {
+        let mut command = tools::command(self.executable());
+        command.arg("--competition").arg(input);
+        command
+    }
+}
@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use malachite::Natural;
+
+use crate::count::Count;
+use crate::utils::ContentHash;
+use super::RestrictedFormula;
+
+/// Directory in which the results of the counters are stored, if the cache is enabled
+static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Enables the cache of the counter results in the given directory
+pub fn set_directory(directory: PathBuf) {
+    if let Err(error) = std::fs::create_dir_all(&directory) {
+        log::warn!("Can not create the counter cache {} ({}), the cache is disabled", directory.display(), error);
+        return;
+    }
+    let _ = DIRECTORY.set(directory);
+}
+
+/// Returns true if the cache is enabled
+pub fn is_enabled() -> bool {
+    DIRECTORY.get().is_some()
+}
+
+/// A result stored in the cache
+pub enum CachedResult {
+    Count(Count),
+    /// The counter did not finish within the given number of seconds
+    Timeout(u64),
+}
+
+/// Returns the key of the restricted formula for the counter: a hash of the identity of the
+/// counter and of the DIMACS of the formula, which is normalized (variables numbered densely,
+/// sorted literals and clauses, without duplicates or tautologies)
+pub fn key(counter: &str, formula: &RestrictedFormula) -> String {
+    let mut normalized = format!("counter {}\n", counter).into_bytes();
+    formula.write_dimacs(&mut normalized).unwrap();
+    let mut hash = ContentHash::new();
+    hash.write(&normalized);
+    hash.finish()
+}
+
+/// Returns the result stored for the key, if the cache is enabled
+pub fn get(key: &str) -> Option<CachedResult> {
+    let content = std::fs::read_to_string(DIRECTORY.get()?.join(key)).ok()?;
+    let (kind, value) = content.trim().split_once(' ')?;
+    match kind {
+        "count" => value.parse::<Natural>().ok().map(|count| CachedResult::Count(Count::exact(count))),
+        "log10" => value.parse::<f64>().ok().map(|log10| CachedResult::Count(Count::from_log10(log10))),
+        "timeout" => value.parse::<u64>().ok().map(CachedResult::Timeout),
+        _ => None,
+    }
+}
+
+/// Stores the result for the key, if the cache is enabled
+pub fn store(key: &str, result: &CachedResult) {
+    let Some(directory) = DIRECTORY.get() else { return; };
+    let content = match result {
+        CachedResult::Count(count) => match count.exact_value() {
+            Some(exact) => format!("count {}\n", exact),
+            None => format!("log10 {}\n", count.log10()),
+        },
+        CachedResult::Timeout(timeout) => format!("timeout {}\n", timeout),
+    };
+    // The entry is written in a temporary file and then renamed, so that an interrupted run
+    // does not leave a truncated entry
+    let temporary = directory.join(format!("{}.{}.tmp", key, std::process::id()));
+    let written = std::fs::write(&temporary, content).and_then(|_| std::fs::rename(&temporary, directory.join(key)));
+    if let Err(error) = written {
+        log::warn!("Can not store the counter result in the cache: {}", error);
+        let _ = std::fs::remove_file(&temporary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+    use crate::problem::Problem;
+    use crate::restricted::Constraint;
+
+    fn formula(dimacs: &str, restrictions: &[Constraint]) -> RestrictedFormula {
+        let mut problem = Problem::from_cnf(parse_dimacs(dimacs.as_bytes()).unwrap(), vec![0, 1, 2], Count::one(), false);
+        problem.new_level();
+        for restriction in restrictions {
+            restriction.apply(&mut problem);
+        }
+        RestrictedFormula::new(&problem)
+    }
+
+    #[test]
+    fn keys_do_not_depend_on_the_order_of_the_clauses() {
+        let reference = key("d4", &formula("p cnf 4 2\n1 -2 0\n2 3 4 0\n", &[]));
+        assert_eq!(key("d4", &formula("p cnf 4 3\n4 3 2 0\n-2 1 0\n1 -2 1 0\n", &[])), reference);
+        assert_eq!(key("d4", &formula("p cnf 4 3\n1 -2 0\n2 3 4 0\n3 -3 0\n", &[])), reference);
+        assert_ne!(key("ganak", &formula("p cnf 4 2\n1 -2 0\n2 3 4 0\n", &[])), reference);
+        assert_ne!(key("d4", &formula("p cnf 4 2\n1 2 0\n2 3 4 0\n", &[])), reference);
+        // Merging 5 with 4 gives the same restricted formula, in which 5 is no longer a variable
+        assert_eq!(key("d4", &formula("p cnf 5 2\n1 -2 0\n2 3 5 0\n", &[Constraint::equality(vec![3, 4], true)])), reference);
+        // 5 is neither in a clause nor in the independent set, so it is not a variable either
+        assert_eq!(key("d4", &formula("p cnf 5 2\n1 -2 0\n2 3 4 0\n", &[])), reference);
+    }
+
+    #[test]
+    fn results_are_read_back() {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-cache", std::process::id()));
+        set_directory(directory.clone());
+        let exact = "123456789012345678901234567890".parse::<Natural>().unwrap();
+        store("test-exact", &CachedResult::Count(Count::exact(exact.clone())));
+        store("test-log10", &CachedResult::Count(Count::from_log10(12.5)));
+        store("test-timeout", &CachedResult::Timeout(30));
+        assert!(matches!(get("test-exact"), Some(CachedResult::Count(count)) if count.exact_value() == Some(&exact)));
+        assert!(matches!(get("test-log10"), Some(CachedResult::Count(count)) if count.exact_value().is_none() && count.log10() == 12.5));
+        assert!(matches!(get("test-timeout"), Some(CachedResult::Timeout(30))));
+        assert!(get("test-missing").is_none());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use malachite::Natural;
+use regex::Regex;
+
+use crate::tools;
+use crate::count::Count;
+use super::{ModelCounter, RestrictedFormula};
+
+/// Splits the command line in arguments on the whitespaces that are not quoted or escaped. Inside
+/// double quotes, a backslash only escapes a double quote or a backslash.
+fn split_arguments(command: &str) -> Result<Vec<String>, String> {
+    let mut arguments = vec![];
+    let mut argument = String::new();
+    // An argument has started, possibly empty (e.g., '')
+    let mut started = false;
+    let mut quote = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => {
+                if started {
+                    arguments.push(std::mem::take(&mut argument));
+                    started = false;
+                }
+                continue;
+            },
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\\') => argument.push(chars.next().ok_or("backslash at the end of the command")?),
+            (Some('"'), '\\') => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => argument.push(escaped),
+                Some(other) => {
+                    argument.push('\\');
+                    argument.push(other);
+                },
+                None => return Err("unterminated quote in the command".to_string()),
+            },
+            (_, c) => argument.push(c),
+        }
+        started = true;
+    }
+    if quote.is_some() {
+        return Err("unterminated quote in the command".to_string());
+    }
+    if started {
+        arguments.push(argument);
+    }
+    Ok(arguments)
+}
+
+/// A model counter described in a configuration file, made of `key = value` lines (lines
+/// starting with '#' are comments):
+///
+/// - `name`: name of the counter in the logs
+/// - `command`: command line of the counter, split on whitespaces. As in a shell, an argument
+///   containing whitespaces is written between single or double quotes, or with the whitespaces
+///   escaped by a backslash. `{input}` is replaced by the path of the formula and `{timeout}` by
+///   the time limit in seconds. Without `{input}`, the formula is given on the standard input.
+/// - `weighted_command`: command line for weighted formulas. The counter supports weights only
+///   if it is given.
+/// - `projection`: `true` if the counter takes the `c p show` line into account
+/// - `log10`: regex whose first group is the log10 of the count
+/// - `count`: regex whose first group is the exact count, preferred to `log10` when it matches
+pub struct CustomCounter {
+    name: String,
+    command: Vec<String>,
+    weighted_command: Option<Vec<String>>,
+    projection: bool,
+    log10: Option<Regex>,
+    count: Option<Regex>,
+}
+
+impl CustomCounter {
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|error| error.to_string())?;
+        let mut name = None;
+        let mut command = None;
+        let mut weighted_command = None;
+        let mut projection = false;
+        let mut log10 = None;
+        let mut count = None;
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|error| error.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected 'key = value', found '{}'", index + 1, line));
+            };
+            let value = value.trim();
+            let regex = |value: &str| Regex::new(value).map_err(|error| format!("line {}: invalid regex: {}", index + 1, error));
+            let arguments = |value: &str| split_arguments(value).map_err(|error| format!("line {}: {}", index + 1, error));
+            match key.trim() {
+                "name" => name = Some(value.to_string()),
+                "command" => command = Some(arguments(value)?),
+                "weighted_command" => weighted_command = Some(arguments(value)?),
+                "projection" => projection = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(format!("line {}: expected true or false, found '{}'", index + 1, value)),
+                },
+                "log10" => log10 = Some(regex(value)?),
+                "count" => count = Some(regex(value)?),
+                key => return Err(format!("line {}: unknown key '{}'", index + 1, key)),
+            }
+        }
+        let command = command.filter(|command| !command.is_empty()).ok_or("missing command")?;
+        if weighted_command.as_ref().is_some_and(|command| command.is_empty()) {
+            return Err("empty weighted_command".to_string());
+        }
+        if log10.is_none() && count.is_none() {
+            return Err("one of the log10 or count regexes is required".to_string());
+        }
+        Ok(Self {
+            name: name.unwrap_or_else(|| command[0].clone()),
+            command,
+            weighted_command,
+            projection,
+            log10,
+            count,
+        })
+    }
+}
+
+impl ModelCounter for CustomCounter {
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn executable(&self) -> &str {
+        &self.command[0]
+    }
+
+    fn identity(&self) -> String {
+        let identity = |command: &Vec<String>| format!("{} ({})", command.join(" "), tools::identity(&command[0]));
+        format!("{} {}", identity(&self.command), self.weighted_command.as_ref().map(identity).unwrap_or_default())
+    }
+
+    fn supports_weights(&self) -> bool {
+        self.weighted_command.is_some()
+    }
+
+    fn supports_projection(&self) -> bool {
+        self.projection
+    }
+
+    fn launch(&self, formula: &RestrictedFormula, input: &Path, timeout: u64) -> Command {
+        let template = match self.weighted_command.as_ref() {
+            Some(weighted_command) if formula.is_weighted() => weighted_command,
+            _ => &self.command,
+        };
+        let input_str = input.to_string_lossy();
+        let mut command = tools::command(&template[0]);
+        command.args(template[1..].iter().map(|arg| arg.replace("{input}", &input_str).replace("{timeout}", &timeout.to_string())));
+        if !template.iter().any(|arg| arg.contains("{input}")) {
+            command.stdin(File::open(input).map(Stdio::from).unwrap_or(Stdio::null()));
+        }
+        command
+    }
+
+    fn parse_output(&self, output: &str) -> Option<Count> {
+        let capture = |regex: &Option<Regex>| regex.as_ref()
+            .and_then(|regex| output.lines().find_map(|line| regex.captures(line)))
+            .and_then(|captures| captures.get(1).map(|group| group.as_str().to_string()));
+        if let Some(count) = capture(&self.count).and_then(|count| count.parse::<Natural>().ok()) {
+            return Some(Count::exact(count));
+        }
+        capture(&self.log10).and_then(|log10| log10.parse::<f64>().ok()).map(Count::from_log10)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::parser::parse_dimacs;
+    use crate::problem::Problem;
+
+    /// Directory of the test, with the stub counter `count.sh` printing the number of clauses of
+    /// its input and its time limit
+    fn stub_directory(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("count.sh"), "#!/bin/sh\necho \"c clauses $(grep -vc '^[cp]' \"$1\")\"\necho \"c timeout $2\"\n").unwrap();
+        directory
+    }
+
+    fn counter(directory: &Path, config: &str) -> Result<CustomCounter, String> {
+        let path = directory.join("counter.conf");
+        let mut file = File::create(&path).unwrap();
+        write!(file, "{}", config.replace("{script}", &directory.join("count.sh").to_string_lossy())).unwrap();
+        CustomCounter::from_file(&path)
+    }
+
+    fn formula() -> RestrictedFormula {
+        let cnf = parse_dimacs("p cnf 3 2\n1 2 0\n-2 3 0\n".as_bytes()).unwrap();
+        RestrictedFormula::new(&Problem::from_cnf(cnf, (0..3).collect(), Count::one(), false))
+    }
+
+    #[test]
+    fn stub_counters_are_run_and_parsed() {
+        let directory = stub_directory("stub");
+        let clauses = counter(&directory, "# stub\nname = stub\ncommand = sh {script} {input} {timeout}\ncount = ^c clauses (\\d+)$\n").unwrap();
+        assert_eq!(clauses.name(), "stub");
+        assert!(!clauses.supports_weights() && !clauses.supports_projection());
+        assert_eq!(clauses.lower_bound(&formula(), 10, None).and_then(|count| count.exact_value().cloned()), Some(Natural::from(2u32)));
+        let timeout = counter(&directory, "command = sh {script} {input} {timeout}\nlog10 = ^c timeout (\\d+)$\nprojection = true\n").unwrap();
+        assert_eq!(timeout.name(), "sh");
+        assert!(timeout.supports_projection());
+        assert_eq!(timeout.lower_bound(&formula(), 7, None).map(|count| count.log10()), Some(7.0));
+        // Without {input}, the formula is given on the standard input: 'p cnf', 'c p show' and the
+        // two clauses
+        let lines = counter(&directory, "command = wc -l\ncount = ^\\s*(\\d+)\n").unwrap();
+        assert_eq!(lines.lower_bound(&formula(), 10, None).and_then(|count| count.exact_value().cloned()), Some(Natural::from(4u32)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn quoted_arguments_may_contain_whitespaces() {
+        assert_eq!(split_arguments(r#"  a 'b c' "d \" \\e" f\ g '' h"'"i\j  "#).unwrap(), vec!["a", "b c", "d \" \\e", "f g", "", "h'ij"]);
+        assert_eq!(split_arguments("a 'b").err(), Some("unterminated quote in the command".to_string()));
+        let directory = stub_directory("quoted arguments");
+        let clauses = counter(&directory, "command = sh '{script}' \"{input}\" {timeout}\ncount = ^c clauses (\\d+)$\n").unwrap();
+        assert_eq!(clauses.lower_bound(&formula(), 10, None).and_then(|count| count.exact_value().cloned()), Some(Natural::from(2u32)));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn invalid_configurations_are_reported() {
+        let directory = stub_directory("invalid");
+        assert_eq!(counter(&directory, "count = (\\d+)\n").err(), Some("missing command".to_string()));
+        assert_eq!(counter(&directory, "command = sh {script}\n").err(), Some("one of the log10 or count regexes is required".to_string()));
+        assert_eq!(counter(&directory, "command = sh {script}\ncount (\\d+)\n").err(), Some("line 2: expected 'key = value', found 'count (\\d+)'".to_string()));
+        assert_eq!(counter(&directory, "command = sh {script}\nlimit = 3\n").err(), Some("line 2: unknown key 'limit'".to_string()));
+        assert!(counter(&directory, "command = sh {script}\ncount = (\\d+\n").is_err());
+        assert_eq!(counter(&directory, "command = sh \"{script}\ncount = (\\d+)\n").err(), Some("line 1: unterminated quote in the command".to_string()));
+        assert!(counter(&directory, "command = sh {script}\ncount = (\\d+)\nprojection = yes\n").is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
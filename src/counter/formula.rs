@@ -0,0 +1,399 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::count::Count;
+use crate::problem::Problem;
+
+/// Directory in which the formulas given to the counters are written, if any
+static DUMP_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Index of the next formula written in the dump directory
+static NEXT_DUMP: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes the formulas given to the counters in the given directory, with the variables of the
+/// input formula
+pub fn set_dump_directory(directory: PathBuf) {
+    if let Err(error) = std::fs::create_dir_all(&directory) {
+        log::warn!("Can not create the directory {} ({}), the formulas are not written", directory.display(), error);
+        return;
+    }
+    let _ = DUMP_DIRECTORY.set(directory);
+}
+
+/// The formula given to the counters to compute a lower bound: the active clauses of a problem in
+/// its current restriction level, in which the variables merged by the restrictions are replaced
+/// by their representative.
+///
+/// Its models are the models of the problem that satisfy the restrictions, projected on the
+/// representatives of the independent set. Merged variables take the same value, so the value of
+/// a projected variable is the value of its representative, even if the representative is not
+/// projected itself, and the count of the formula is not above the count of the problem.
+///
+/// The formula is simplified before it is given to a counter: the variables are numbered densely,
+/// tautologies and duplicate literals and clauses are removed, and the unit clauses are propagated.
+pub struct RestrictedFormula {
+    number_var: usize,
+    /// Clauses in DIMACS numbering, with sorted literals, sorted and without duplicates
+    clauses: Vec<Vec<isize>>,
+    /// Variables, starting at 0, on which the count is projected, sorted
+    projection: Vec<usize>,
+    /// True if the count of the problem is projected. Otherwise the projection set is an
+    /// independent support of the formula and the counters may count all its models.
+    projected: bool,
+    /// Weights of the positive and negative literals of each variable, if the problem is weighted
+    weights: Option<Vec<(f64, f64)>>,
+    /// Product of the weights of the literals fixed by the unit clauses, by which the count of the
+    /// formula must be multiplied
+    factor: Count,
+    /// True if the unit propagation found a conflict
+    unsatisfiable: bool,
+    /// Number of variables of the input formula
+    number_input_var: usize,
+    /// Variable of the input formula of each variable
+    input_variables: Vec<usize>,
+    /// Literals of the input formula fixed by the unit clauses, with their weights, counted in the
+    /// factor of the formula
+    input_units: Vec<(isize, f64)>,
+}
+
+/// Applies unit propagation on the clauses. Returns the value of the variables fixed by the unit
+/// clauses, and keeps in the clauses only the ones that are not satisfied, without their false
+/// literals. Returns None on a conflict.
+fn propagate_units(number_var: usize, clauses: &mut Vec<Vec<isize>>) -> Option<Vec<Option<bool>>> {
+    let mut value = vec![None; number_var];
+    let mut occurrences = vec![vec![]; number_var];
+    let mut units = vec![];
+    for (index, clause) in clauses.iter().enumerate() {
+        for &l in clause.iter() {
+            occurrences[l.unsigned_abs() - 1].push(index);
+        }
+        if clause.len() == 1 {
+            units.push(clause[0]);
+        }
+    }
+    let mut satisfied = vec![false; clauses.len()];
+    while let Some(unit) = units.pop() {
+        let variable = unit.unsigned_abs() - 1;
+        match value[variable] {
+            Some(v) if v != (unit > 0) => return None,
+            Some(_) => continue,
+            None => value[variable] = Some(unit > 0),
+        }
+        for &index in occurrences[variable].iter() {
+            if satisfied[index] {
+                continue;
+            }
+            if clauses[index].contains(&unit) {
+                satisfied[index] = true;
+                continue;
+            }
+            clauses[index].retain(|&l| l != -unit);
+            match clauses[index].len() {
+                0 => return None,
+                1 => units.push(clauses[index][0]),
+                _ => {},
+            }
+        }
+    }
+    let mut index = 0;
+    clauses.retain(|_| {
+        index += 1;
+        !satisfied[index - 1]
+    });
+    Some(value)
+}
+
+impl RestrictedFormula {
+
+    /// Builds the formula of the problem in its current restriction level
+    pub fn new(problem: &Problem) -> Self {
+        if problem.is_contradictory() {
+            return Self::unsatisfiable(problem);
+        }
+        let mut clauses = problem.iter_clauses().filter_map(|clause| {
+            let mut clause = clause.clone();
+            clause.sort_unstable_by_key(|l| (l.unsigned_abs(), *l));
+            clause.dedup();
+            let tautology = clause.windows(2).any(|pair| pair[0] == -pair[1]);
+            (!tautology).then_some(clause)
+        }).collect::<Vec<Vec<isize>>>();
+        // The representatives counted by the formula are the ones of the independent set, even if
+        // the count is not projected: the other variables are either determined by them or do not
+        // appear in the clauses (e.g., they have been eliminated by the pre-processing), and
+        // keeping the latter would count them as free variables.
+        let mut counted = vec![false; problem.number_var()];
+        for variable in problem.iter_independent_set() {
+            counted[problem.literal_repr(variable as isize + 1).unsigned_abs() - 1] = true;
+        }
+        let Some(mut value) = propagate_units(problem.number_var(), &mut clauses) else {
+            return Self::unsatisfiable(problem);
+        };
+        for literal in problem.iter_fixed_literals() {
+            value[literal.unsigned_abs() - 1] = Some(literal > 0);
+        }
+        // The weights of a representative are the product of the weights of the variables it
+        // represents, since they take the same value, as maintained by the substitutions of the
+        // problem
+        let mut factor = Count::one();
+        if problem.is_weighted() {
+            let log10_factor = (0..problem.number_var()).filter(|&v| counted[v]).filter_map(|v| value[v].map(|value| {
+                let (pos, neg) = problem.weight(v);
+                if value { pos.log10() } else { neg.log10() }
+            })).sum::<f64>();
+            factor = Count::from_log10(log10_factor);
+        }
+        // The variables kept are the ones that are not fixed and that either appear in a clause or
+        // are counted
+        let mut occurs = vec![false; problem.number_var()];
+        for &l in clauses.iter().flatten() {
+            occurs[l.unsigned_abs() - 1] = true;
+        }
+        let mut renumbering = vec![0; problem.number_var()];
+        let mut number_var = 0;
+        for (variable, number) in renumbering.iter_mut().enumerate() {
+            if value[variable].is_none() && (occurs[variable] || counted[variable]) {
+                number_var += 1;
+                *number = number_var;
+            }
+        }
+        let mut clauses = clauses.into_iter().map(|clause| {
+            let mut clause = clause.into_iter().map(|l| {
+                let variable = renumbering[l.unsigned_abs() - 1] as isize;
+                if l < 0 { -variable } else { variable }
+            }).collect::<Vec<isize>>();
+            clause.sort_unstable_by_key(|l| (l.unsigned_abs(), *l));
+            clause
+        }).collect::<Vec<Vec<isize>>>();
+        clauses.sort_unstable();
+        clauses.dedup();
+        let projection = (0..problem.number_var()).filter(|&v| counted[v] && renumbering[v] != 0).map(|v| renumbering[v] - 1).collect::<Vec<usize>>();
+        let weights = problem.is_weighted().then(|| {
+            (0..problem.number_var()).filter(|&v| renumbering[v] != 0).map(|v| problem.weight(v)).collect::<Vec<(f64, f64)>>()
+        });
+        let input_variables = (0..problem.number_var()).filter(|&v| renumbering[v] != 0).map(|v| problem.input_variable(v)).collect();
+        let input_units = (0..problem.number_var()).filter(|&v| counted[v]).filter_map(|v| value[v].map(|value| {
+            let (pos, neg) = problem.weight(v);
+            let literal = v as isize + 1;
+            if value { (problem.input_literal(literal), pos) } else { (problem.input_literal(-literal), neg) }
+        })).collect();
+        Self {
+            number_var,
+            clauses,
+            projection,
+            projected: problem.is_projected(),
+            weights,
+            factor,
+            unsatisfiable: false,
+            number_input_var: problem.number_input_var(),
+            input_variables,
+            input_units,
+        }
+    }
+
+    fn unsatisfiable(problem: &Problem) -> Self {
+        Self {
+            number_var: 0,
+            clauses: vec![vec![]],
+            projection: vec![],
+            projected: problem.is_projected(),
+            weights: problem.is_weighted().then(Vec::new),
+            factor: Count::zero(),
+            unsatisfiable: true,
+            number_input_var: problem.number_input_var(),
+            input_variables: vec![],
+            input_units: vec![],
+        }
+    }
+
+    pub fn number_var(&self) -> usize {
+        self.number_var
+    }
+
+    pub fn number_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+
+    /// Iterates on the clauses, in DIMACS numbering
+    pub fn iter_clauses(&self) -> impl Iterator<Item = &Vec<isize>> {
+        self.clauses.iter()
+    }
+
+    /// Returns the variables, starting at 0, on which the count is projected
+    pub fn projection(&self) -> &[usize] {
+        &self.projection
+    }
+
+    /// Returns true if the count of the problem is projected
+    pub fn is_projected(&self) -> bool {
+        self.projected
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// Returns the weights of the positive and negative literals of the variable
+    pub fn weight(&self, variable: usize) -> (f64, f64) {
+        match &self.weights {
+            Some(weights) => weights[variable],
+            None => (1.0, 1.0),
+        }
+    }
+
+    /// Returns the factor by which the count of the formula must be multiplied
+    pub fn factor(&self) -> &Count {
+        &self.factor
+    }
+
+    /// Returns true if the simplification showed that the formula has no model
+    pub fn is_unsatisfiable(&self) -> bool {
+        self.unsatisfiable
+    }
+
+    /// Writes the formula in DIMACS, with the projection set and, for weighted problems, the
+    /// weights of the literals. The writer should be buffered.
+    pub fn write_dimacs(&self, writer: &mut dyn Write) -> io::Result<()> {
+        if self.is_weighted() {
+            writeln!(writer, "c t pwmc")?;
+        }
+        writeln!(writer, "p cnf {} {}", self.number_var, self.clauses.len())?;
+        write!(writer, "c p show")?;
+        for variable in self.projection.iter() {
+            write!(writer, " {}", variable + 1)?;
+        }
+        writeln!(writer, " 0")?;
+        if let Some(weights) = self.weights.as_ref() {
+            for (variable, (pos, neg)) in weights.iter().enumerate() {
+                writeln!(writer, "c p weight {} {} 0", variable + 1, pos)?;
+                writeln!(writer, "c p weight -{} {} 0", variable + 1, neg)?;
+            }
+        }
+        for clause in self.clauses.iter() {
+            for l in clause.iter() {
+                write!(writer, "{} ", l)?;
+            }
+            writeln!(writer, "0")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the formula in DIMACS with the variables of the input formula. The literals fixed by
+    /// the unit clauses are written as unit clauses, so that the count of the written formula is
+    /// the count of the restricted formula, factor included.
+    pub fn write_input_dimacs(&self, writer: &mut dyn Write) -> io::Result<()> {
+        let input_literal = |l: isize| {
+            let variable = self.input_variables[l.unsigned_abs() - 1] as isize + 1;
+            if l < 0 { -variable } else { variable }
+        };
+        if self.is_weighted() {
+            writeln!(writer, "c t pwmc")?;
+        }
+        writeln!(writer, "p cnf {} {}", self.number_input_var, self.clauses.len() + self.input_units.len())?;
+        write!(writer, "c p show")?;
+        for variable in self.projection.iter().map(|&v| input_literal(v as isize + 1)).chain(self.input_units.iter().map(|(l, _)| l.abs())) {
+            write!(writer, " {}", variable)?;
+        }
+        writeln!(writer, " 0")?;
+        if let Some(weights) = self.weights.as_ref() {
+            for (variable, (pos, neg)) in weights.iter().enumerate() {
+                writeln!(writer, "c p weight {} {} 0", input_literal(variable as isize + 1), pos)?;
+                writeln!(writer, "c p weight -{} {} 0", input_literal(variable as isize + 1), neg)?;
+            }
+            for (literal, weight) in self.input_units.iter() {
+                writeln!(writer, "c p weight {} {} 0", literal, weight)?;
+            }
+        }
+        for (literal, _) in self.input_units.iter() {
+            writeln!(writer, "{} 0", literal)?;
+        }
+        for clause in self.clauses.iter() {
+            for &l in clause.iter() {
+                write!(writer, "{} ", input_literal(l))?;
+            }
+            writeln!(writer, "0")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the formula, with the variables of the input formula, in the dump directory if one
+    /// is set
+    pub fn dump(&self) {
+        let Some(directory) = DUMP_DIRECTORY.get() else { return; };
+        let path = directory.join(format!("formula-{}.cnf", NEXT_DUMP.fetch_add(1, Ordering::Relaxed)));
+        let written = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            self.write_input_dimacs(&mut writer)?;
+            writer.flush()
+        });
+        match written {
+            Ok(()) => log::debug!("Restricted formula written in {}", path.display()),
+            Err(error) => log::warn!("Can not write the restricted formula in {}: {}", path.display(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+    use crate::restricted::Constraint;
+
+    fn formula(dimacs: &str, independent_set: Vec<usize>, projected: bool, restrictions: &[Constraint]) -> RestrictedFormula {
+        let mut problem = Problem::from_cnf(parse_dimacs(dimacs.as_bytes()).unwrap(), independent_set, Count::one(), projected);
+        problem.new_level();
+        for restriction in restrictions {
+            restriction.apply(&mut problem);
+        }
+        RestrictedFormula::new(&problem)
+    }
+
+    fn dimacs(formula: &RestrictedFormula) -> String {
+        let mut written = vec![];
+        formula.write_dimacs(&mut written).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn unused_and_merged_variables_are_removed() {
+        // 5 neither occurs nor is projected
+        let unused = formula("p cnf 5 2\n1 2 0\n3 4 0\n", vec![0, 2], true, &[]);
+        assert_eq!(dimacs(&unused), "p cnf 4 2\nc p show 1 3 0\n1 2 0\n3 4 0\n");
+        // 2 and 4 are merged, the clause '2 3 4' loses a literal and '2 -4' is a tautology
+        let merged = formula("p cnf 5 4\n1 -2 0\n2 3 4 0\n-4 5 0\n2 -4 0\n", (0..5).collect(), false, &[Constraint::equality(vec![1, 3], true)]);
+        assert_eq!((merged.number_var(), merged.number_clauses(), merged.projection().len()), (4, 3, 4));
+        assert!(merged.iter_clauses().all(|clause| clause.len() == 2));
+    }
+
+    #[test]
+    fn dumped_formulas_use_the_variables_of_the_input() {
+        let input = parse_dimacs("p cnf 7 5\n1 2 0\n-2 3 0\n4 5 0\n-5 -6 0\n7 0\n".as_bytes()).unwrap();
+        let bpe = parse_dimacs("p cnf 7 3\n1 3 0\n4 -6 0\n7 0\n".as_bytes()).unwrap();
+        let mut problem = Problem::from_preprocessed(&input, bpe, vec![0, 2, 3, 5, 6], Count::one(), false);
+        problem.new_level();
+        // Merges the input variables 3 and 4
+        Constraint::equality(vec![1, 2], true).apply(&mut problem);
+        let formula = RestrictedFormula::new(&problem);
+        assert_eq!(dimacs(&formula), "p cnf 3 2\nc p show 1 2 3 0\n1 2 0\n2 -3 0\n");
+        let mut written = vec![];
+        formula.write_input_dimacs(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), "p cnf 7 3\nc p show 1 3 6 7 0\n7 0\n1 3 0\n3 -6 0\n");
+    }
+
+    #[test]
+    fn units_are_propagated_into_the_factor() {
+        let weighted = formula("p cnf 3 2\nc p weight 1 0.25 0\nc p weight -1 0.75 0\n1 0\n-1 2 3 0\n", (0..3).collect(), false, &[]);
+        assert!(!weighted.is_unsatisfiable());
+        assert_eq!(weighted.iter_clauses().cloned().collect::<Vec<Vec<isize>>>(), vec![vec![1, 2]]);
+        assert!((weighted.factor().log10() - 0.25f64.log10()).abs() < 1e-12);
+        // The units fix 1 and 2, which then falsify the last clause
+        let conflict = formula("p cnf 3 3\n1 0\n-1 2 0\n-1 -2 0\n", (0..3).collect(), false, &[]);
+        assert!(conflict.is_unsatisfiable());
+        assert_eq!(conflict.factor().exact_value(), Some(&malachite::Natural::from(0u32)));
+        // The units '-1' and '2' contradict once 1 and 2 are merged
+        let merged = formula("p cnf 2 2\n-1 0\n2 0\n", (0..2).collect(), false, &[Constraint::equality(vec![0, 1], true)]);
+        assert!(merged.is_unsatisfiable());
+    }
+}
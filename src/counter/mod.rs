@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, exit};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use clap::ValueEnum;
+use malachite::Natural;
+use rustc_hash::FxHashMap;
+
+use crate::count::Count;
+use crate::tools;
+use crate::supervisor::{Outcome, Supervised};
+use cache::CachedResult;
+
+mod backends;
+mod custom;
+mod formula;
+mod native;
+pub mod cache;
+
+pub use backends::{D4, Ganak, SharpsatTd, Gpmc, ExactMc};
+pub use custom::CustomCounter;
+pub use formula::{RestrictedFormula, set_dump_directory};
+pub use native::{BruteForce, Dpll};
+
+/// Index of the next input file written for a counter
+static NEXT_INPUT: AtomicUsize = AtomicUsize::new(0);
+
+/// The model counters that can be selected on the command line
+#[derive(Clone, ValueEnum)]
+pub enum Counter {
+    D4,
+    Ganak,
+    SharpsatTd,
+    Gpmc,
+    #[value(name = "exactmc")]
+    ExactMc,
+    /// A counter described in the file given by --counter-config
+    Custom,
+    /// Built-in enumeration of the assignments, for tiny formulas
+    BruteForce,
+    /// Built-in DPLL counter with component caching, for moderate formulas
+    Dpll,
+}
+
+impl Counter {
+
+    /// Returns the model counter, reading its description from the configuration file for a
+    /// custom counter
+    pub fn build(&self, config: Option<&Path>) -> Box<dyn ModelCounter> {
+        match self {
+            Self::D4 => Box::new(D4),
+            Self::Ganak => Box::new(Ganak),
+            Self::SharpsatTd => Box::new(SharpsatTd),
+            Self::Gpmc => Box::new(Gpmc),
+            Self::ExactMc => Box::new(ExactMc),
+            Self::BruteForce => Box::new(BruteForce),
+            Self::Dpll => Box::new(Dpll),
+            Self::Custom => {
+                let Some(config) = config else {
+                    log::error!("The custom counter requires a configuration file (--counter-config)");
+                    exit(1);
+                };
+                match CustomCounter::from_file(config) {
+                    Ok(counter) => Box::new(counter),
+                    Err(error) => {
+                        log::error!("Can not read the counter configuration {}: {}", config.display(), error);
+                        exit(1);
+                    },
+                }
+            },
+        }
+    }
+}
+
+/// A model counter. The restricted formula is written in a file, an external counter is launched
+/// on it and the count is parsed from its output. The native counters count it in-process.
+pub trait ModelCounter: Send + Sync {
+
+    /// Name of the counter, used in the logs
+    fn name(&self) -> &str;
+
+    /// Executable launched by the counter, checked at startup
+    fn executable(&self) -> &str;
+
+    /// Returns true if the counter runs in-process, without an executable to check
+    fn is_native(&self) -> bool {
+        false
+    }
+
+    /// Identifies the counter in the keys of the result cache: its name and, for the external
+    /// counters, the path and version of its executable
+    fn identity(&self) -> String {
+        if self.is_native() {
+            return self.name().to_string();
+        }
+        format!("{} {}", self.name(), tools::identity(self.executable()))
+    }
+
+    /// Returns true if the counter can compute weighted model counts
+    fn supports_weights(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the counter takes the projection set (`c p show`) into account
+    fn supports_projection(&self) -> bool {
+        false
+    }
+
+    /// Writes the restricted formula given to the counter
+    fn prepare_input(&self, formula: &RestrictedFormula, writer: &mut dyn Write) -> io::Result<()> {
+        formula.write_dimacs(writer)
+    }
+
+    /// Returns the command counting the formula in the file `input`. The command is killed after
+    /// `timeout` seconds.
+    fn launch(&self, formula: &RestrictedFormula, input: &Path, timeout: u64) -> Command;
+
+    /// Returns the count from the standard output of the counter, if any
+    fn parse_output(&self, output: &str) -> Option<Count> {
+        parse_competition_output(output)
+    }
+
+    /// Counts the restricted formula. By default the formula is written in a file by
+    /// `prepare_input` and the command returned by `launch` is run on it by the supervisor.
+    fn count(&self, formula: &RestrictedFormula, timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Outcome<Count> {
+        // Several counters may run at the same time, each on its own input file
+        let input = std::env::temp_dir().join(format!("couaincre-{}-counter-{}.cnf", std::process::id(), NEXT_INPUT.fetch_add(1, Ordering::Relaxed)));
+        let written = File::create(&input).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            self.prepare_input(formula, &mut writer)?;
+            writer.flush()
+        });
+        if let Err(error) = written {
+            log::error!("Can not write the input of {} in {}: {}", self.name(), input.display(), error);
+            exit(1);
+        }
+        log::trace!("Launching {} on formula with {} variables and {} clauses with {} seconds timeout", self.name(), formula.number_var(), formula.number_clauses(), timeout);
+        let outcome = Supervised::new(self.name(), self.launch(formula, &input, timeout)).timeout(timeout).cancel_flag(cancel).run(|_, output| self.parse_output(output));
+        let _ = std::fs::remove_file(&input);
+        outcome
+    }
+
+    /// Computes the count of the restricted formula, or None if the counter did not finish in
+    /// time or has been cancelled by setting the `cancel` flag
+    fn lower_bound(&self, formula: &RestrictedFormula, timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Option<Count> {
+        if formula.is_unsatisfiable() {
+            log::trace!("The restricted formula is unsatisfiable");
+            return Some(Count::zero());
+        }
+        formula.dump();
+        // Hashing the formula is only worth it if the result can be stored
+        let key = cache::is_enabled().then(|| cache::key(&self.identity(), formula));
+        match key.as_deref().and_then(cache::get) {
+            Some(CachedResult::Count(count)) => {
+                log::trace!("Count of the restricted formula found in the cache");
+                return Some(count.multiply(formula.factor()));
+            },
+            Some(CachedResult::Timeout(limit)) if limit >= timeout => {
+                log::trace!("{} already timed out on the restricted formula with {} seconds", self.name(), limit);
+                return None;
+            },
+            _ => {},
+        }
+        match self.count(formula, timeout, cancel) {
+            Outcome::Solved(count) => {
+                if let Some(key) = key.as_deref() {
+                    cache::store(key, &CachedResult::Count(count.clone()));
+                }
+                Some(count.multiply(formula.factor()))
+            },
+            Outcome::Timeout => {
+                if let Some(key) = key.as_deref() {
+                    cache::store(key, &CachedResult::Timeout(timeout));
+                }
+                None
+            },
+            Outcome::Cancelled => None,
+            Outcome::MemOut => {
+                log::warn!("{} reached the memory limit", self.name());
+                None
+            },
+            Outcome::Crashed(status) => {
+                log::warn!("{} crashed ({})", self.name(), status);
+                None
+            },
+            Outcome::ParseFailure => {
+                log::warn!("No count in the output of {}", self.name());
+                None
+            },
+            Outcome::LaunchFailure(_) | Outcome::Unsupported => None,
+        }
+    }
+}
+
+/// Returns the count given in the model counting competition output format: the exact count of
+/// the `s mc`, `s pmc` or `c s exact arb int` lines if any, otherwise the `c s log10-estimate`
+/// line
+pub fn parse_competition_output(output: &str) -> Option<Count> {
+    let last_token = |prefixes: &[&str]| output.lines()
+        .find(|line| prefixes.iter().any(|prefix| line.starts_with(prefix)))
+        .and_then(|line| line.split_whitespace().last());
+    if let Some(count) = last_token(&["s mc ", "s pmc ", "c s exact arb int "]).and_then(|count| count.parse::<Natural>().ok()) {
+        return Some(Count::exact(count));
+    }
+    last_token(&["c s log10-estimate "]).and_then(|count| count.parse::<f64>().ok()).map(Count::from_log10)
+}
+
+fn union(map: &mut FxHashMap<usize, usize>, x: usize, y: usize) {
+    let repr_x = find(map, x);
+    let repr_y = find(map, y);
+    if repr_x != repr_y {
+        map.insert(repr_x, repr_y);
+    }
+}
+
+fn find(map: &mut FxHashMap<usize, usize>, x: usize) -> usize {
+    let repr = *map.entry(x).or_insert(x);
+    if repr == x {
+        return repr;
+    }
+    let repr = find(map, map[&x]);
+    map.insert(x, repr);
+    repr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_counts_are_preferred_to_estimates() {
+        let output = "c o solving
+c s log10-estimate 19.2
+s mc 123456789012345678901
+";
+        assert_eq!(parse_competition_output(output).unwrap().exact_value(), Some(&"123456789012345678901".parse::<Natural>().unwrap()));
+        let output = "s SATISFIABLE
+c s type pmc
+c s log10-estimate 3.5
+";
+        let count = parse_competition_output(output).unwrap();
+        assert_eq!((count.exact_value(), count.log10()), (None, 3.5));
+        assert_eq!(parse_competition_output("c s exact arb int 42
+").unwrap().exact_value(), Some(&Natural::from(42u32)));
+        assert!(parse_competition_output("s UNKNOWN
+").is_none());
+    }
+}
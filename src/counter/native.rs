@@ -0,0 +1,579 @@
+use std::path::Path;
+use std::process::{Command, exit};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use malachite::Natural;
+use malachite::base::num::basic::traits::{One, Zero};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::count::Count;
+use crate::supervisor::Outcome;
+use super::{ModelCounter, RestrictedFormula, find, union};
+
+/// Maximum number of variables occurring in the clauses for which the brute-force counter
+/// enumerates the assignments
+const BRUTE_FORCE_MAX_VARIABLES: usize = 24;
+
+/// Number of steps of the search between two checks of the time limit and of the cancel flag
+const CHECK_INTERVAL: usize = 1 << 10;
+
+/// Maximum number of components kept in the cache of the DPLL counter. The cache is emptied when
+/// it is full.
+const CACHE_CAPACITY: usize = 1 << 20;
+
+/// Stack size of the thread running the search, which recurses once per decision
+const SEARCH_STACK_SIZE: usize = 256 << 20;
+
+/// A count computed by the native counters: exact for unweighted formulas, and the log10 of the
+/// count for weighted ones
+trait Value: Clone + Send {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn is_zero(&self) -> bool;
+    fn into_count(self) -> Count;
+}
+
+impl Value for Natural {
+
+    fn zero() -> Self {
+        Natural::ZERO
+    }
+
+    fn one() -> Self {
+        Natural::ONE
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Natural::ZERO
+    }
+
+    fn into_count(self) -> Count {
+        Count::exact(self)
+    }
+}
+
+/// The log10 of a weighted count, so that the products of many weights do not overflow
+#[derive(Clone)]
+struct Log10(f64);
+
+impl Log10 {
+
+    fn from_weight(weight: f64) -> Self {
+        Self(weight.log10())
+    }
+}
+
+impl Value for Log10 {
+
+    fn zero() -> Self {
+        Self(f64::NEG_INFINITY)
+    }
+
+    fn one() -> Self {
+        Self(0.0)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        let (max, min) = if self.0 >= other.0 { (self.0, other.0) } else { (other.0, self.0) };
+        if min == f64::NEG_INFINITY {
+            return Self(max);
+        }
+        Self(max + (10f64.powf(min - max)).ln_1p() / std::f64::consts::LN_10)
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Self(self.0 + other.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == f64::NEG_INFINITY
+    }
+
+    fn into_count(self) -> Count {
+        Count::from_log10(self.0)
+    }
+}
+
+/// Reason for which a native counter stopped before computing the count
+enum Interrupt {
+    Timeout,
+    Cancelled,
+    /// The formula has too many variables for the counter
+    TooLarge(usize),
+}
+
+/// Time limit and cancel flag of a native counter, checked regularly during the search
+struct Limits {
+    /// None if the time limit is too far to be represented
+    deadline: Option<Instant>,
+    cancel: Option<Arc<AtomicBool>>,
+    steps: usize,
+}
+
+impl Limits {
+
+    fn new(timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Self {
+        Self {
+            deadline: Instant::now().checked_add(Duration::from_secs(timeout)),
+            cancel,
+            steps: 0,
+        }
+    }
+
+    fn check(&mut self) -> Result<(), Interrupt> {
+        self.steps += 1;
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+        if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+            return Err(Interrupt::Cancelled);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(Interrupt::Timeout);
+        }
+        Ok(())
+    }
+}
+
+fn variable(literal: isize) -> usize {
+    literal.unsigned_abs() - 1
+}
+
+/// The restricted formula counted by the native counters. Literals are in DIMACS numbering and
+/// the clauses have no duplicate literals nor tautologies.
+struct Formula<V: Value> {
+    number_var: usize,
+    clauses: Vec<Vec<isize>>,
+    /// Variables on which the count is projected, all of them if the problem is not projected
+    projected: Vec<bool>,
+    /// Weights of the positive and negative literals of each variable
+    weights: Vec<(V, V)>,
+}
+
+impl<V: Value> Formula<V> {
+
+    /// Builds the formula from the restricted formula, with the weights converted by `weight`
+    fn new(formula: &RestrictedFormula, weight: impl Fn(f64) -> V) -> Self {
+        let mut projected = vec![!formula.is_projected(); formula.number_var()];
+        for &variable in formula.projection() {
+            projected[variable] = true;
+        }
+        Self {
+            number_var: formula.number_var(),
+            clauses: formula.iter_clauses().cloned().collect(),
+            projected,
+            weights: (0..formula.number_var()).map(|variable| {
+                let (pos, neg) = formula.weight(variable);
+                (weight(pos), weight(neg))
+            }).collect(),
+        }
+    }
+
+    fn literal_weight(&self, literal: isize) -> &V {
+        let (pos, neg) = &self.weights[variable(literal)];
+        if literal > 0 { pos } else { neg }
+    }
+
+    /// Returns the factor contributed by a variable that appears in no clause
+    fn free_weight(&self, variable: usize) -> V {
+        if self.projected[variable] {
+            self.weights[variable].0.add(&self.weights[variable].1)
+        } else {
+            V::one()
+        }
+    }
+
+    fn is_model(&self, assignment: &[bool]) -> bool {
+        self.clauses.iter().all(|clause| clause.iter().any(|&l| assignment[variable(l)] == (l > 0)))
+    }
+}
+
+/// A counting algorithm of the native counters
+trait Algorithm: Sync {
+    fn count<V: Value>(&self, formula: &Formula<V>, limits: &mut Limits) -> Result<V, Interrupt>;
+}
+
+/// Counts the restricted formula with the algorithm, in a thread with a stack large enough for
+/// the recursion of the search
+fn count_restricted(name: &str, algorithm: &impl Algorithm, formula: &RestrictedFormula, timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Outcome<Count> {
+    log::trace!("Counting formula with {} variables and {} clauses with {} and {} seconds timeout", formula.number_var(), formula.number_clauses(), name, timeout);
+    let mut limits = Limits::new(timeout, cancel);
+    let result = std::thread::scope(|scope| {
+        let search = std::thread::Builder::new().stack_size(SEARCH_STACK_SIZE).spawn_scoped(scope, || {
+            if formula.is_weighted() {
+                algorithm.count(&Formula::new(formula, Log10::from_weight), &mut limits).map(Value::into_count)
+            } else {
+                algorithm.count(&Formula::new(formula, |_| Natural::ONE), &mut limits).map(Value::into_count)
+            }
+        });
+        match search {
+            Ok(search) => search.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)),
+            Err(error) => {
+                log::error!("Can not start the search of {}: {}", name, error);
+                exit(1);
+            },
+        }
+    });
+    match result {
+        Ok(count) => Outcome::Solved(count),
+        Err(Interrupt::Timeout) => Outcome::Timeout,
+        Err(Interrupt::Cancelled) => Outcome::Cancelled,
+        Err(Interrupt::TooLarge(number_var)) => {
+            log::warn!("{} can not count a formula with {} variables", name, number_var);
+            Outcome::Unsupported
+        },
+    }
+}
+
+/// Counts the models by enumerating the assignments of the variables occurring in the clauses.
+/// Only suited to tiny formulas, and used as ground truth for the other counters.
+pub struct BruteForce;
+
+impl Algorithm for BruteForce {
+
+    fn count<V: Value>(&self, formula: &Formula<V>, limits: &mut Limits) -> Result<V, Interrupt> {
+        let mut occurring = vec![false; formula.number_var];
+        for clause in formula.clauses.iter() {
+            for &l in clause.iter() {
+                occurring[variable(l)] = true;
+            }
+        }
+        let (projected, hidden): (Vec<usize>, Vec<usize>) = (0..formula.number_var).filter(|&v| occurring[v]).partition(|&v| formula.projected[v]);
+        if projected.len() + hidden.len() > BRUTE_FORCE_MAX_VARIABLES {
+            return Err(Interrupt::TooLarge(projected.len() + hidden.len()));
+        }
+        let mut count = V::zero();
+        let mut assignment = vec![false; formula.number_var];
+        for projected_bits in 0..(1u64 << projected.len()) {
+            for (index, &v) in projected.iter().enumerate() {
+                assignment[v] = projected_bits & (1 << index) != 0;
+            }
+            // The assignment of the projected variables is counted once if any assignment of the
+            // other variables extends it to a model
+            let mut satisfiable = false;
+            for hidden_bits in 0..(1u64 << hidden.len()) {
+                limits.check()?;
+                for (index, &v) in hidden.iter().enumerate() {
+                    assignment[v] = hidden_bits & (1 << index) != 0;
+                }
+                if formula.is_model(&assignment) {
+                    satisfiable = true;
+                    break;
+                }
+            }
+            if satisfiable {
+                let weight = projected.iter().fold(V::one(), |weight, &v| {
+                    weight.mul(formula.literal_weight(if assignment[v] { v as isize + 1 } else { -(v as isize + 1) }))
+                });
+                count = count.add(&weight);
+            }
+        }
+        Ok((0..formula.number_var).filter(|&v| !occurring[v]).fold(count, |count, v| count.mul(&formula.free_weight(v))))
+    }
+}
+
+impl ModelCounter for BruteForce {
+
+    fn name(&self) -> &str {
+        "brute-force"
+    }
+
+    fn executable(&self) -> &str {
+        self.name()
+    }
+
+    fn is_native(&self) -> bool {
+        true
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn supports_projection(&self) -> bool {
+        true
+    }
+
+    fn launch(&self, _formula: &RestrictedFormula, _input: &Path, _timeout: u64) -> Command {
+        unreachable!("{} counts in-process", self.name())
+    }
+
+    fn count(&self, formula: &RestrictedFormula, timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Outcome<Count> {
+        count_restricted(self.name(), self, formula, timeout, cancel)
+    }
+}
+
+/// Assigns the literals and applies unit propagation on the clauses. Returns the remaining
+/// clauses, without their false literals, and all the assigned literals, or None on a conflict.
+fn propagate(clauses: &[Vec<isize>], literals: &[isize]) -> Option<(Vec<Vec<isize>>, Vec<isize>)> {
+    let mut value = FxHashMap::<usize, bool>::default();
+    let mut assigned = vec![];
+    let mut pending = literals.to_vec();
+    let mut remaining = clauses.to_vec();
+    loop {
+        for literal in pending.drain(..) {
+            match value.get(&variable(literal)) {
+                Some(&v) if v != (literal > 0) => return None,
+                Some(_) => {},
+                None => {
+                    value.insert(variable(literal), literal > 0);
+                    assigned.push(literal);
+                },
+            }
+        }
+        let mut reduced_clauses = Vec::with_capacity(remaining.len());
+        for clause in remaining {
+            if clause.iter().any(|&l| value.get(&variable(l)) == Some(&(l > 0))) {
+                continue;
+            }
+            let reduced = clause.into_iter().filter(|&l| !value.contains_key(&variable(l))).collect::<Vec<isize>>();
+            match reduced.len() {
+                0 => return None,
+                1 => pending.push(reduced[0]),
+                _ => reduced_clauses.push(reduced),
+            }
+        }
+        remaining = reduced_clauses;
+        if pending.is_empty() {
+            return Some((remaining, assigned));
+        }
+    }
+}
+
+/// Splits the clauses in connected components, two clauses being connected if they share a
+/// variable
+fn components(clauses: Vec<Vec<isize>>) -> Vec<Vec<Vec<isize>>> {
+    let mut map = FxHashMap::<usize, usize>::default();
+    for clause in clauses.iter() {
+        for &l in clause.iter() {
+            union(&mut map, variable(clause[0]), variable(l));
+        }
+    }
+    let mut components = FxHashMap::<usize, Vec<Vec<isize>>>::default();
+    for clause in clauses {
+        let repr = find(&mut map, variable(clause[0]));
+        components.entry(repr).or_default().push(clause);
+    }
+    components.into_values().collect()
+}
+
+/// Counts the models with a DPLL search that splits the formula in independent components and
+/// caches the count of each component. Projected counts are computed by branching on the
+/// projected variables first and then checking the satisfiability of the remaining components.
+pub struct Dpll;
+
+/// State of a search of the DPLL counter
+struct DpllSearch<'a, V: Value> {
+    formula: &'a Formula<V>,
+    limits: &'a mut Limits,
+    /// Count of the components already solved, keyed by their sorted clauses
+    cache: FxHashMap<Vec<Vec<isize>>, V>,
+}
+
+impl<V: Value> DpllSearch<'_, V> {
+
+    /// Returns the count of the clauses, which contain the given variables, after the literals
+    /// have been assigned
+    fn branch(&mut self, clauses: &[Vec<isize>], variables: &[usize], literals: &[isize]) -> Result<V, Interrupt> {
+        let Some((remaining, assigned)) = propagate(clauses, literals) else { return Ok(V::zero()); };
+        let mut count = assigned.iter().filter(|&&l| self.formula.projected[variable(l)]).fold(V::one(), |count, &l| count.mul(self.formula.literal_weight(l)));
+        // The variables that are neither assigned nor in a remaining clause are free
+        let constrained = assigned.iter().map(|&l| variable(l)).chain(remaining.iter().flatten().map(|&l| variable(l))).collect::<FxHashSet<usize>>();
+        for &v in variables.iter().filter(|v| !constrained.contains(v)) {
+            count = count.mul(&self.formula.free_weight(v));
+        }
+        for component in components(remaining) {
+            if count.is_zero() {
+                break;
+            }
+            count = count.mul(&self.count_component(component)?);
+        }
+        Ok(count)
+    }
+
+    fn count_component(&mut self, mut clauses: Vec<Vec<isize>>) -> Result<V, Interrupt> {
+        self.limits.check()?;
+        for clause in clauses.iter_mut() {
+            clause.sort_unstable();
+        }
+        clauses.sort_unstable();
+        if let Some(count) = self.cache.get(&clauses) {
+            return Ok(count.clone());
+        }
+        let mut occurrences = FxHashMap::<usize, usize>::default();
+        for &l in clauses.iter().flatten() {
+            *occurrences.entry(variable(l)).or_default() += 1;
+        }
+        let decision = occurrences.iter().filter(|&(&v, _)| self.formula.projected[v]).max_by_key(|&(&v, &n)| (n, v)).map(|(&v, _)| v);
+        let count = match decision {
+            Some(decision) => {
+                let variables = occurrences.keys().copied().collect::<Vec<usize>>();
+                let literal = decision as isize + 1;
+                let positive = self.branch(&clauses, &variables, &[literal])?;
+                positive.add(&self.branch(&clauses, &variables, &[-literal])?)
+            },
+            // Only variables that are not projected remain: the component counts for one if it
+            // is satisfiable
+            None => if self.is_satisfiable(&clauses)? { V::one() } else { V::zero() },
+        };
+        if self.cache.len() >= CACHE_CAPACITY {
+            self.cache.clear();
+        }
+        self.cache.insert(clauses, count.clone());
+        Ok(count)
+    }
+
+    fn is_satisfiable(&mut self, clauses: &[Vec<isize>]) -> Result<bool, Interrupt> {
+        self.limits.check()?;
+        let Some(clause) = clauses.iter().min_by_key(|clause| clause.len()) else { return Ok(true); };
+        for literal in [clause[0], -clause[0]] {
+            if let Some((remaining, _)) = propagate(clauses, &[literal]) && self.is_satisfiable(&remaining)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Algorithm for Dpll {
+
+    fn count<V: Value>(&self, formula: &Formula<V>, limits: &mut Limits) -> Result<V, Interrupt> {
+        let mut search = DpllSearch {
+            formula,
+            limits,
+            cache: FxHashMap::default(),
+        };
+        let variables = (0..formula.number_var).collect::<Vec<usize>>();
+        search.branch(&formula.clauses, &variables, &[])
+    }
+}
+
+impl ModelCounter for Dpll {
+
+    fn name(&self) -> &str {
+        "dpll"
+    }
+
+    fn executable(&self) -> &str {
+        self.name()
+    }
+
+    fn is_native(&self) -> bool {
+        true
+    }
+
+    fn supports_weights(&self) -> bool {
+        true
+    }
+
+    fn supports_projection(&self) -> bool {
+        true
+    }
+
+    fn launch(&self, _formula: &RestrictedFormula, _input: &Path, _timeout: u64) -> Command {
+        unreachable!("{} counts in-process", self.name())
+    }
+
+    fn count(&self, formula: &RestrictedFormula, timeout: u64, cancel: Option<Arc<AtomicBool>>) -> Outcome<Count> {
+        count_restricted(self.name(), self, formula, timeout, cancel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+    use crate::problem::Problem;
+    use crate::restricted::Constraint;
+
+    fn formula(dimacs: &str, independent_set: Vec<usize>, restrictions: &[Constraint]) -> RestrictedFormula {
+        let cnf = parse_dimacs(dimacs.as_bytes()).unwrap();
+        let projected = independent_set.len() < cnf.number_var;
+        let mut problem = Problem::from_cnf(cnf, independent_set, Count::one(), projected);
+        problem.new_level();
+        for restriction in restrictions {
+            restriction.apply(&mut problem);
+        }
+        RestrictedFormula::new(&problem)
+    }
+
+    /// Returns the counts of the brute-force and the DPLL counters, which must both succeed
+    fn counts(formula: &RestrictedFormula) -> (Count, Count) {
+        let solved = |outcome: Outcome<Count>| match outcome {
+            Outcome::Solved(count) => count,
+            _ => panic!("the formula is not counted"),
+        };
+        (solved(ModelCounter::count(&BruteForce, formula, 10, None)), solved(ModelCounter::count(&Dpll, formula, 10, None)))
+    }
+
+    fn exact_counts(formula: &RestrictedFormula) -> (Natural, Natural) {
+        let (brute_force, dpll) = counts(formula);
+        (brute_force.exact_value().unwrap().clone(), dpll.exact_value().unwrap().clone())
+    }
+
+    #[test]
+    fn counts_of_known_formulas() {
+        // x1 => x2 => ... => x8: the models are the 9 thresholds
+        let chain = (1..8).map(|v| format!("-{} {} 0\n", v, v + 1)).collect::<String>();
+        let nine = Natural::from(9u32);
+        assert_eq!(exact_counts(&formula(&format!("p cnf 8 7\n{}", chain), (0..8).collect(), &[])), (nine.clone(), nine));
+        // At most one of 6 variables, and 2 free variables
+        let at_most_one = (1..=6).flat_map(|u| (u + 1..=6).map(move |v| format!("-{} -{} 0\n", u, v))).collect::<String>();
+        let twenty_eight = Natural::from(28u32);
+        assert_eq!(exact_counts(&formula(&format!("p cnf 8 15\n{}", at_most_one), (0..8).collect(), &[])), (twenty_eight.clone(), twenty_eight));
+        // Merging 1 and 2 in the chain leaves the 8 thresholds where they are equal
+        let merged = formula(&format!("p cnf 8 7\n{}", chain), (0..8).collect(), &[Constraint::equality(vec![0, 1], true)]);
+        let eight = Natural::from(8u32);
+        assert_eq!(exact_counts(&merged), (eight.clone(), eight));
+        let unsat = formula("p cnf 3 4\n1 2 0\n-1 2 0\n1 -2 0\n-1 -2 0\n", (0..3).collect(), &[]);
+        assert_eq!(exact_counts(&unsat), (Natural::ZERO, Natural::ZERO));
+    }
+
+    #[test]
+    fn projected_variables_are_counted_once() {
+        // 3 = 1 xor 2, projected on 3 and 4: 3 takes both values and 4 is free
+        let xor = "p cnf 4 4\n-1 -2 -3 0\n1 2 -3 0\n1 -2 3 0\n-1 2 3 0\n";
+        let four = Natural::from(4u32);
+        assert_eq!(exact_counts(&formula(xor, vec![2, 3], &[])), (four.clone(), four));
+        // Projected on 1 and 2, with 3 forced to true: 1 xor 2 must hold
+        let forced = format!("{}3 0\n", xor.replace("p cnf 4 4", "p cnf 4 5"));
+        let two = Natural::from(2u32);
+        assert_eq!(exact_counts(&formula(&forced, vec![0, 1], &[])), (two.clone(), two));
+    }
+
+    #[test]
+    fn weighted_counts_agree() {
+        // (1 | 2) & (-2 | 3) with weights, the expected count is computed by enumeration
+        let dimacs = "p cnf 3 2\nc p weight 1 0.5 0\nc p weight -1 0.5 0\nc p weight 2 0.2 0\nc p weight -2 0.8 0\nc p weight 3 3 0\n1 2 0\n-2 3 0\n";
+        let weight = |v: usize, value: bool| [[0.5, 0.5], [0.8, 0.2], [1.0, 3.0]][v][value as usize];
+        let expected = (0..8usize).map(|bits| [bits & 1 != 0, bits & 2 != 0, bits & 4 != 0])
+            .filter(|a| (a[0] || a[1]) && (!a[1] || a[2]))
+            .map(|a| (0..3).map(|v| weight(v, a[v])).product::<f64>())
+            .sum::<f64>();
+        let (brute_force, dpll) = counts(&formula(dimacs, (0..3).collect(), &[]));
+        assert!((brute_force.log10() - expected.log10()).abs() < 1e-9);
+        assert!((dpll.log10() - expected.log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn brute_force_does_not_handle_large_formulas() {
+        // A cycle of implications: all the variables are equal
+        let number_var = BRUTE_FORCE_MAX_VARIABLES + 1;
+        let dimacs = format!("p cnf {} {}\n{}", number_var, number_var, (1..=number_var).map(|v| format!("{} -{} 0\n", v, v % number_var + 1)).collect::<String>());
+        let formula = formula(&dimacs, (0..number_var).collect(), &[]);
+        assert!(matches!(ModelCounter::count(&BruteForce, &formula, 10, None), Outcome::Unsupported));
+        assert!(matches!(ModelCounter::count(&Dpll, &formula, 10, None), Outcome::Solved(count) if count.exact_value() == Some(&Natural::from(2u32))));
+    }
+}
@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use tempfile::TempPath;
+
+use crate::parser::{Cnf, ParseError, ParseErrorKind, parse_dimacs, parse_dimacs_file};
+
+/// Compression format of an input file, detected from its first bytes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+
+    /// Detects the compression of the file from its magic bytes
+    pub fn detect(path: &Path) -> std::io::Result<Self> {
+        let mut magic = [0u8; 6];
+        let mut file = File::open(path)?;
+        let mut read = 0;
+        while read < magic.len() {
+            let n = file.read(&mut magic[read..])?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        let magic = &magic[..read];
+        let compression = if magic.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Self::Xz
+        } else if magic.starts_with(b"BZh") {
+            Self::Bzip2
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else {
+            Self::None
+        };
+        Ok(compression)
+    }
+
+    /// Returns a reader decompressing the file, or None if the file is not compressed
+    fn decoder(&self, file: File) -> std::io::Result<Option<Box<dyn Read>>> {
+        let file = BufReader::new(file);
+        let decoder: Box<dyn Read> = match self {
+            Self::None => return Ok(None),
+            Self::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+            Self::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(file)),
+            Self::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(file)),
+            Self::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+        };
+        Ok(Some(decoder))
+    }
+}
+
+/// Path to a plain (uncompressed) DIMACS file for the input, to be given to the external tools.
+/// When the input is compressed, this is a decompressed copy in a file created in the temporary
+/// directory, which is removed when this structure is dropped.
+pub struct PlainInput {
+    path: PathBuf,
+    _temporary: Option<TempPath>,
+}
+
+impl PlainInput {
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Reader that copies everything it reads into a writer.
+struct TeeReader<R: Read, W: Write> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.writer.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+fn io_error(error: std::io::Error) -> ParseError {
+    ParseError {
+        line: 0,
+        column: 0,
+        kind: ParseErrorKind::Io(error),
+    }
+}
+
+/// Reads the input DIMACS file, possibly compressed. Compressed files are decompressed as a stream
+/// into the parser and, at the same time, into a temporary plain file for the external tools.
+pub fn read_input(path: &Path) -> Result<(Cnf, PlainInput), ParseError> {
+    let compression = Compression::detect(path).map_err(io_error)?;
+    let Some(decoder) = compression.decoder(File::open(path).map_err(io_error)?).map_err(io_error)? else {
+        let cnf = parse_dimacs_file(path)?;
+        return Ok((cnf, PlainInput { path: path.to_path_buf(), _temporary: None }));
+    };
+    log::info!("Input file is compressed ({:?}), decompressing it", compression);
+    let (file, temporary) = tempfile::Builder::new().prefix("couaincre-").suffix(".cnf").tempfile().map_err(io_error)?.into_parts();
+    let plain = PlainInput {
+        path: temporary.to_path_buf(),
+        _temporary: Some(temporary),
+    };
+    let mut tee = TeeReader {
+        reader: decoder,
+        writer: BufWriter::new(file),
+    };
+    // On a parse error, the rest of the stream is not decompressed since the input is rejected
+    let cnf = parse_dimacs(&mut tee)?;
+    // The parser may stop before the end of the file (e.g., '%' end marker), the remaining of the
+    // stream is still copied so that the external tools see the same file. A corrupted stream is
+    // reported even if the parser stopped before the error.
+    std::io::copy(&mut tee, &mut std::io::sink()).and_then(|_| tee.writer.flush()).map_err(io_error)?;
+    Ok((cnf, plain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMACS: &str = "p cnf 3 2\n1 -2 0\n2 3 0\n";
+
+    fn compress(compression: Compression, data: &str) -> Vec<u8> {
+        let data = data.as_bytes();
+        match compression {
+            Compression::None => data.to_vec(),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish()).unwrap()
+            },
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+                encoder.write_all(data).and_then(|_| encoder.finish()).unwrap()
+            },
+            Compression::Bzip2 => {
+                let mut encoder = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+                encoder.write_all(data).and_then(|_| encoder.finish()).unwrap()
+            },
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn compressed_inputs_are_decompressed_in_temporary_files() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Xz, Compression::Bzip2, Compression::Zstd] {
+            let mut input = tempfile::NamedTempFile::new().unwrap();
+            input.write_all(&compress(compression, DIMACS)).unwrap();
+            assert_eq!(Compression::detect(input.path()).unwrap(), compression);
+            let (cnf, plain) = read_input(input.path()).unwrap();
+            assert_eq!(cnf.clauses, vec![vec![1, -2], vec![2, 3]]);
+            assert_eq!(std::fs::read_to_string(plain.path()).unwrap(), DIMACS);
+            let path = plain.path().to_path_buf();
+            drop(plain);
+            assert_eq!(path.exists(), compression == Compression::None, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn corrupted_inputs_are_reported() {
+        let mut data = compress(Compression::Gzip, DIMACS);
+        data.truncate(data.len() - 4);
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        input.write_all(&data).unwrap();
+        assert!(matches!(read_input(input.path()), Err(ParseError { kind: ParseErrorKind::Io(_), .. })));
+        // The reading stops at the first parse error, before reaching the corrupted end
+        let mut data = compress(Compression::Gzip, &format!("p cnf 3 2\n1 x 0\n{}", "2 3 0\n".repeat(100_000)));
+        data.truncate(data.len() - 4);
+        let mut input = tempfile::NamedTempFile::new().unwrap();
+        input.write_all(&data).unwrap();
+        assert!(matches!(read_input(input.path()), Err(ParseError { line: 2, column: 3, kind: ParseErrorKind::InvalidLiteral(_) })));
+    }
+}
@@ -1,50 +1,152 @@
 mod problem;
+mod parser;
+mod input;
 mod tree_decomposition;
 mod restricted;
 mod utils;
 mod counter;
+mod count;
+mod supervisor;
+mod tools;
 
 use clap::Parser;
 use clap_verbosity_flag::{Verbosity, InfoLevel};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::exit;
 use std::time::Instant;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, OnceLock};
 
-use restricted::{RestrictedSolver, RestrictedMethod, EqualityHeuristic};
-use counter::Counter;
+use restricted::{RestrictedSolver, RestrictedMethod};
+use counter::{Counter, ModelCounter};
+use tree_decomposition::{ContractionHeuristic, EliminationHeuristic, TreeDecomposition, read_tree_decomposition};
+use problem::{IndependentSetCache, Preprocessor, SatSolver, default_cache_dir};
 
 #[derive(Parser)]
 #[clap(name="Couaincre", version, author, about)]
 pub struct Args {
-    #[clap(short, long, value_parser)]
-    /// The input CNF in DIMACS format
-    input: PathBuf,
+    #[clap(short, long, value_parser, required_unless_present_any=["list_cache", "clear_cache"])]
+    /// The input CNF in DIMACS format, possibly compressed (gzip, xz, bzip2 or zstd)
+    input: Option<PathBuf>,
+    #[clap(long)]
+    /// Directory of the independent set cache (default: $XDG_CACHE_HOME/couaincre)
+    cache_dir: Option<PathBuf>,
+    #[clap(long)]
+    /// Do not read nor write the independent set cache
+    no_cache: bool,
+    #[clap(long)]
+    /// Recompute the independent set and replace the cached one
+    refresh_cache: bool,
+    #[clap(long)]
+    /// Print the entries of the independent set cache and exit
+    list_cache: bool,
+    #[clap(long)]
+    /// Remove the entries of the independent set cache and exit
+    clear_cache: bool,
+    #[clap(long)]
+    /// Run arjun to shrink the projection set declared in the input, if any
+    minimize_projection: bool,
+    #[clap(long)]
+    /// Time limit, in seconds, of arjun computing the independent set (no limit by default)
+    arjun_timeout: Option<u64>,
     #[clap(long, default_value_t=10)]
     /// Timeout for the pre-processing
     preproc_timeout: usize,
+    #[clap(long, value_enum, default_value_t=SatSolver::Cryptominisat)]
+    /// Which SAT solver checks the satisfiability of the input, within the pre-processing timeout
+    sat_solver: SatSolver,
+    #[clap(long, value_enum, default_value_t=Preprocessor::Bpe)]
+    /// Which pre-processor to run on the input formula
+    preprocessor: Preprocessor,
     #[clap(long, default_value_t=u64::MAX)]
     /// Timeout for the solving
     timeout: u64,
+    #[clap(long)]
+    /// Memory limit, in megabytes, of each external tool, on the resident memory of the tool and
+    /// of the processes it launches
+    memory_limit: Option<u64>,
+    #[clap(long)]
+    /// Directory in which the results of the counter are cached, keyed by restricted formula
+    count_cache: Option<PathBuf>,
+    #[clap(long)]
+    /// Directory in which each restricted formula given to the counter is written, in DIMACS with
+    /// the variables of the input formula
+    dump_formulas: Option<PathBuf>,
+    #[clap(long, default_value_t=1)]
+    /// Number of restriction levels counted in parallel. When a level is counted, the counters
+    /// still running on more restricted levels are stopped.
+    jobs: usize,
     #[clap(long, default_value_t=50)]
     /// Maximum width of a tree decomposition at which it is consider it can be solved exactly
     td_threshold: usize,
+    #[clap(long, value_enum, value_delimiter=',', default_value="lazy-min-fill")]
+    /// Elimination heuristics computing the tree decompositions, separated by commas. The first
+    /// one always completes, the other ones run in parallel within --td-heuristics-timeout
+    /// seconds, and the decomposition of smallest width is kept.
+    td_heuristics: Vec<EliminationHeuristic>,
+    #[clap(long, default_value_t=10)]
+    /// Time limit, in seconds, of the elimination heuristics after the first one
+    td_heuristics_timeout: u64,
+    #[clap(long, default_value_t=100)]
+    /// Maximum number of contraction rounds. The contraction stops as soon as the decrease of the
+    /// treewidth or of its lower bound suggests that more rounds are needed to reach --td-threshold.
+    td_max_rounds: usize,
+    #[clap(long)]
+    /// Directory in which the tree decomposition of the primal graph of each component is written,
+    /// in PACE .td format, with the variables of the input formula as vertices
+    td_output: Option<PathBuf>,
+    #[clap(long)]
+    /// Tree decomposition of the primal graph, in PACE .td format, with the variables of the input
+    /// formula as vertices, as the decompositions written with --td-output. Its width replaces the
+    /// min-fill estimate whenever it fits the primal graph of the restricted problem.
+    td_file: Option<PathBuf>,
+    #[clap(long)]
+    /// External tree decomposition solver following the PACE conventions (e.g. flow_cutter_pace17),
+    /// used instead of the min-fill heuristic to estimate the treewidth
+    td_solver: Option<String>,
+    #[clap(long, default_value_t=10)]
+    /// Time limit, in seconds, of each run of the tree decomposition solver
+    td_solver_timeout: u64,
     #[clap(long, value_enum, default_value_t=Counter::D4)]
     /// Which model counter to use when computing the model count of restricted and relaxed
     /// formulas
     counter: Counter,
-    #[clap(long, value_enum, default_value_t=EqualityHeuristic::MaxDegMostCommon)]
-    contraction_heuristic: EqualityHeuristic,
+    #[clap(long)]
+    /// Configuration file giving the paths of the external tools, as 'name = path' lines. The
+    /// COUAINCRE_<NAME> environment variables (e.g. COUAINCRE_D4) take precedence.
+    tools_config: Option<PathBuf>,
+    #[clap(long)]
+    /// Configuration file describing the command and output of the custom counter
+    counter_config: Option<PathBuf>,
+    #[clap(long, value_enum, default_value_t=ContractionHeuristic::MaxDegMostCommon)]
+    contraction_heuristic: ContractionHeuristic,
     #[clap(long, default_value_t=RestrictedMethod::Equality)]
     restricted_method: RestrictedMethod,
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
+    #[clap(skip)]
+    model_counter: OnceLock<Box<dyn ModelCounter>>,
+    #[clap(skip)]
+    td_file_decomposition: OnceLock<TreeDecomposition>,
 }
 
 impl Args {
 
-    pub fn counter(&self) -> &Counter {
-        &self.counter
+    pub fn input(&self) -> &Path {
+        self.input.as_deref().unwrap()
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir.clone().unwrap_or_else(default_cache_dir)
+    }
+
+    pub fn counter(&self) -> &dyn ModelCounter {
+        self.model_counter.get_or_init(|| self.counter.build(self.counter_config.as_deref())).as_ref()
+    }
+
+    /// Returns the tree decomposition read from the --td-file file
+    pub fn td_file_decomposition(&self) -> Option<&TreeDecomposition> {
+        self.td_file_decomposition.get()
     }
 }
 
@@ -54,12 +156,7 @@ pub struct Controller {
 
 impl Controller {
     fn remaining(&self, timeout: u64) -> u64 {
-        let elapsed = self.start.elapsed().as_secs();
-        if elapsed >= timeout {
-            0
-        } else {
-            timeout - elapsed
-        }
+        timeout.saturating_sub(self.start.elapsed().as_secs())
     }
 
     fn elapsed(&self) -> u64 {
@@ -74,10 +171,47 @@ static CTRL: LazyLock<Controller> = LazyLock::new(|| {
 });
 
 fn main() {
-    let args = Args::parse();
+    let mut args = Args::parse();
     env_logger::Builder::new().filter_level(args.verbose.log_level_filter()).init();
-    utils::check_executables(&args);
+    if args.list_cache || args.clear_cache {
+        let cache = IndependentSetCache::new(args.cache_dir());
+        if args.list_cache {
+            cache.list();
+        }
+        if args.clear_cache {
+            println!("Removed {} entries from the independent set cache", cache.clear());
+        }
+        exit(0);
+    }
+    if let Some(memory_limit) = args.memory_limit {
+        supervisor::set_memory_limit(memory_limit);
+    }
+    // The probes of the tools must not go through the counter cache
+    tools::discover(&mut args);
+    if let Some(count_cache) = args.count_cache.as_ref() {
+        counter::cache::set_directory(count_cache.clone());
+    }
+    if let Some(dump_formulas) = args.dump_formulas.as_ref() {
+        counter::set_dump_directory(dump_formulas.clone());
+    }
+    if let Some(path) = args.td_file.as_ref() {
+        match read_tree_decomposition(path) {
+            Ok(decomposition) => { let _ = args.td_file_decomposition.set(decomposition); },
+            Err(error) => {
+                log::error!("Can not read the tree decomposition {}: {}", path.display(), error);
+                exit(1);
+            },
+        }
+    }
     let mut restricted_solver = RestrictedSolver::new(&args);
+    if restricted_solver.is_weighted() && !args.counter().supports_weights() {
+        log::error!("The formula is weighted but {} does not support weighted model counting", args.counter().name());
+        exit(1);
+    }
+    if restricted_solver.is_projected() && !args.counter().supports_projection() {
+        log::error!("The formula declares a projection set but {} does not support projected model counting", args.counter().name());
+        exit(1);
+    }
     match args.restricted_method {
         RestrictedMethod::Equality => restricted_solver.solve(&args),
         RestrictedMethod::Xor => restricted_solver.xor_solve(&args),
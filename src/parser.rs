@@ -0,0 +1,347 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// A CNF formula as read from a DIMACS file. Variables are numbered from 1 in the clauses, as in
+/// the DIMACS format.
+pub struct Cnf {
+    pub number_var: usize,
+    pub clauses: Vec<Vec<isize>>,
+    /// Literal weights given by `c p weight <lit> <w> 0` lines, in the order of the file
+    pub weights: Vec<(isize, f64)>,
+    /// Projection set given by `c p show ... 0` or `c ind ... 0` lines, if any
+    pub projection: Option<Vec<usize>>,
+}
+
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Io(std::io::Error),
+    MissingHeader,
+    DuplicateHeader,
+    InvalidHeader(String),
+    InvalidLiteral(String),
+    InvalidWeight(String),
+    InvalidProjection(String),
+    VariableOutOfRange(usize, usize),
+    UnterminatedClause,
+    ClauseCountMismatch(usize, usize),
+}
+
+/// Error returned when a DIMACS file can not be parsed. The line and column (both starting at 1)
+/// give the position of the offending token in the input. Errors only detected at the end of the
+/// input (e.g., missing clauses) point just after the last character of the input.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "I/O error: {}", error),
+            Self::MissingHeader => write!(f, "no 'p cnf' header before the first clause"),
+            Self::DuplicateHeader => write!(f, "more than one 'p cnf' header"),
+            Self::InvalidHeader(header) => write!(f, "invalid header '{}'", header),
+            Self::InvalidLiteral(token) => write!(f, "invalid literal '{}'", token),
+            Self::InvalidWeight(line) => write!(f, "invalid weight line '{}'", line),
+            Self::InvalidProjection(line) => write!(f, "invalid projection line '{}'", line),
+            Self::VariableOutOfRange(variable, number_var) => write!(f, "variable {} is above the {} variables declared in the header", variable, number_var),
+            Self::UnterminatedClause => write!(f, "last clause is not terminated by 0"),
+            Self::ClauseCountMismatch(declared, found) => write!(f, "header declares {} clauses but {} were found", declared, found),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Streaming DIMACS parser. The input is read line by line and clauses may span several lines.
+/// Comment lines are skipped, except for the weight and projection lines, and a line starting with
+/// '%' (SATLIB end marker) stops the parsing.
+struct Parser {
+    line: usize,
+    header: Option<(usize, usize)>,
+    clauses: Vec<Vec<isize>>,
+    // Weights and projected variables with the line at which they are declared. They are checked
+    // against the header at the end of the parsing since these lines may appear before it.
+    weights: Vec<(usize, isize, f64)>,
+    projection: Option<Vec<(usize, usize)>>,
+    current: Vec<isize>,
+    // Position of the first literal of the current clause, used to report unterminated clauses
+    current_start: (usize, usize),
+    // Position just after the last character read, used to report errors at the end of the input
+    end: (usize, usize),
+}
+
+impl Parser {
+
+    fn new() -> Self {
+        Self {
+            line: 0,
+            header: None,
+            clauses: vec![],
+            weights: vec![],
+            projection: None,
+            current: vec![],
+            current_start: (0, 0),
+            end: (1, 1),
+        }
+    }
+
+    fn error(&self, column: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            line: self.line,
+            column,
+            kind,
+        }
+    }
+
+    fn end_error(&self, kind: ParseErrorKind) -> ParseError {
+        let (line, column) = self.end;
+        ParseError {
+            line,
+            column,
+            kind,
+        }
+    }
+
+    fn parse_header(&mut self, line: &str) -> Result<(), ParseError> {
+        if self.header.is_some() {
+            return Err(self.error(1, ParseErrorKind::DuplicateHeader));
+        }
+        let mut split = line.split_whitespace();
+        if split.next() != Some("p") || split.next() != Some("cnf") {
+            return Err(self.error(1, ParseErrorKind::InvalidHeader(line.trim().to_string())));
+        }
+        let number_var = split.next().and_then(|s| s.parse::<usize>().ok());
+        let number_clauses = split.next().and_then(|s| s.parse::<usize>().ok());
+        match (number_var, number_clauses, split.next()) {
+            (Some(number_var), Some(number_clauses), None) => {
+                self.header = Some((number_var, number_clauses));
+                Ok(())
+            },
+            _ => Err(self.error(1, ParseErrorKind::InvalidHeader(line.trim().to_string()))),
+        }
+    }
+
+    fn parse_comment(&mut self, line: &str) -> Result<(), ParseError> {
+        let mut split = line.split_whitespace();
+        if split.next() != Some("c") {
+            return Ok(());
+        }
+        match split.next() {
+            Some("p") => match split.next() {
+                Some("weight") => self.parse_weight(line, split),
+                Some("show") => self.parse_projection(line, split),
+                _ => Ok(()),
+            },
+            Some("ind") => self.parse_projection(line, split),
+            _ => Ok(()),
+        }
+    }
+
+    fn parse_projection<'a>(&mut self, line: &str, split: impl Iterator<Item = &'a str>) -> Result<(), ParseError> {
+        let mut variables = vec![];
+        let mut terminated = false;
+        for token in split {
+            match token.parse::<usize>() {
+                Ok(_) if terminated => return Err(self.error(1, ParseErrorKind::InvalidProjection(line.trim().to_string()))),
+                Ok(0) => terminated = true,
+                Ok(variable) => variables.push((self.line, variable)),
+                Err(_) => return Err(self.error(1, ParseErrorKind::InvalidProjection(line.trim().to_string()))),
+            }
+        }
+        self.projection.get_or_insert_default().extend(variables);
+        Ok(())
+    }
+
+    fn parse_weight<'a>(&mut self, line: &str, mut split: impl Iterator<Item = &'a str>) -> Result<(), ParseError> {
+        let literal = split.next().and_then(|s| s.parse::<isize>().ok());
+        let weight = split.next().and_then(|s| s.parse::<f64>().ok());
+        match (literal, weight, split.next(), split.next()) {
+            (Some(literal), Some(weight), None | Some("0"), None) if literal != 0 && weight.is_finite() && weight >= 0.0 => {
+                self.weights.push((self.line, literal, weight));
+                Ok(())
+            },
+            _ => Err(self.error(1, ParseErrorKind::InvalidWeight(line.trim().to_string()))),
+        }
+    }
+
+    fn parse_clause_line(&mut self, line: &str) -> Result<(), ParseError> {
+        let mut tokens = line.char_indices().peekable();
+        while let Some(&(start, c)) = tokens.peek() {
+            if c.is_whitespace() {
+                tokens.next();
+                continue;
+            }
+            let mut end = start;
+            while let Some(&(index, c)) = tokens.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                end = index + c.len_utf8();
+                tokens.next();
+            }
+            let token = &line[start..end];
+            let column = start + 1;
+            let number_var = match self.header {
+                Some((number_var, _)) => number_var,
+                None => return Err(self.error(column, ParseErrorKind::MissingHeader)),
+            };
+            let literal = match token.parse::<isize>() {
+                Ok(literal) => literal,
+                Err(_) => return Err(self.error(column, ParseErrorKind::InvalidLiteral(token.to_string()))),
+            };
+            if literal == 0 {
+                self.clauses.push(std::mem::take(&mut self.current));
+                continue;
+            }
+            if literal.unsigned_abs() > number_var {
+                return Err(self.error(column, ParseErrorKind::VariableOutOfRange(literal.unsigned_abs(), number_var)));
+            }
+            if self.current.is_empty() {
+                self.current_start = (self.line, column);
+            }
+            self.current.push(literal);
+        }
+        Ok(())
+    }
+
+    fn parse<R: BufRead>(mut self, mut reader: R) -> Result<Cnf, ParseError> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            self.line += 1;
+            match reader.read_line(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => {},
+                Err(error) => return Err(self.error(1, ParseErrorKind::Io(error))),
+            };
+            self.end = (self.line, buffer.trim_end_matches(['\n', '\r']).chars().count() + 1);
+            let line = buffer.trim_start();
+            if line.starts_with('c') {
+                self.parse_comment(line)?;
+                continue;
+            }
+            if line.starts_with('%') {
+                break;
+            }
+            if line.starts_with('p') {
+                self.parse_header(line)?;
+                continue;
+            }
+            self.parse_clause_line(&buffer)?;
+        }
+        if !self.current.is_empty() {
+            let (line, column) = self.current_start;
+            return Err(ParseError {
+                line,
+                column,
+                kind: ParseErrorKind::UnterminatedClause,
+            });
+        }
+        let (number_var, number_clauses) = match self.header {
+            Some(header) => header,
+            None => return Err(self.end_error(ParseErrorKind::MissingHeader)),
+        };
+        if number_clauses != self.clauses.len() {
+            return Err(self.end_error(ParseErrorKind::ClauseCountMismatch(number_clauses, self.clauses.len())));
+        }
+        let declared = self.weights.iter().map(|&(line, literal, _)| (line, literal.unsigned_abs())).chain(self.projection.iter().flatten().copied());
+        for (line, variable) in declared {
+            if variable > number_var {
+                return Err(ParseError {
+                    line,
+                    column: 1,
+                    kind: ParseErrorKind::VariableOutOfRange(variable, number_var),
+                });
+            }
+        }
+        Ok(Cnf {
+            number_var,
+            clauses: self.clauses,
+            weights: self.weights.into_iter().map(|(_, literal, weight)| (literal, weight)).collect(),
+            projection: self.projection.map(|projection| projection.into_iter().map(|(_, variable)| variable).collect()),
+        })
+    }
+}
+
+/// Parses a CNF formula in DIMACS format from the given reader.
+pub fn parse_dimacs<R: Read>(reader: R) -> Result<Cnf, ParseError> {
+    Parser::new().parse(BufReader::new(reader))
+}
+
+/// Parses the DIMACS file at the given path.
+pub fn parse_dimacs_file(path: &Path) -> Result<Cnf, ParseError> {
+    match File::open(path) {
+        Ok(file) => parse_dimacs(file),
+        Err(error) => Err(ParseError {
+            line: 0,
+            column: 0,
+            kind: ParseErrorKind::Io(error),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the line and column of the error, and its message
+    fn error(dimacs: &str) -> (usize, usize, String) {
+        match parse_dimacs(dimacs.as_bytes()) {
+            Ok(_) => panic!("the formula is parsed"),
+            Err(error) => (error.line, error.column, error.kind.to_string()),
+        }
+    }
+
+    #[test]
+    fn clauses_may_span_several_lines() {
+        let cnf = parse_dimacs("c comment\np cnf 3 2\n1 -2\n 3 0 -1 0\n%\n0\n".as_bytes()).unwrap();
+        assert_eq!(cnf.number_var, 3);
+        assert_eq!(cnf.clauses, vec![vec![1, -2, 3], vec![-1]]);
+    }
+
+    #[test]
+    fn errors_give_the_position_of_the_token() {
+        assert_eq!(error("1 2 0\n"), (1, 1, "no 'p cnf' header before the first clause".to_string()));
+        assert_eq!(error("p cnf 3 1\n1  x2 0\n"), (2, 4, "invalid literal 'x2'".to_string()));
+        assert_eq!(error("p cnf 3 2\n1 0\n  -2 4 0\n"), (3, 6, "variable 4 is above the 3 variables declared in the header".to_string()));
+        assert_eq!(error("p cnf 3 1\np cnf 3 1\n"), (2, 1, "more than one 'p cnf' header".to_string()));
+        assert_eq!(error("p cnf three 1\n"), (1, 1, "invalid header 'p cnf three 1'".to_string()));
+    }
+
+    #[test]
+    fn errors_at_the_end_of_the_input_are_on_the_last_line() {
+        assert_eq!(error(""), (1, 1, "no 'p cnf' header before the first clause".to_string()));
+        assert_eq!(error("c only comments\n"), (1, 16, "no 'p cnf' header before the first clause".to_string()));
+        assert_eq!(error("p cnf 3 2\n1 0\n"), (2, 4, "header declares 2 clauses but 1 were found".to_string()));
+        assert_eq!(error("p cnf 3 3\n1 0\n2 0"), (3, 4, "header declares 3 clauses but 2 were found".to_string()));
+        assert_eq!(error("p cnf 3 2\n1 0\n2\n -3\n"), (3, 1, "last clause is not terminated by 0".to_string()));
+    }
+
+    #[test]
+    fn weights_are_read_from_comments() {
+        let cnf = parse_dimacs("c p weight 1 0.25 0\np cnf 2 1\nc p weight -2 3\nc plain comment\n1 2 0\n".as_bytes()).unwrap();
+        assert_eq!(cnf.weights, vec![(1, 0.25), (-2, 3.0)]);
+        assert_eq!(error("p cnf 3 1\nc p weight 5 0.5 0\n1 0\n"), (2, 1, "variable 5 is above the 3 variables declared in the header".to_string()));
+        assert_eq!(error("p cnf 3 1\nc p weight 1 -2 0\n1 0\n"), (2, 1, "invalid weight line 'c p weight 1 -2 0'".to_string()));
+        assert_eq!(error("p cnf 3 1\nc p weight 0 1 0\n1 0\n").0, 2);
+    }
+
+    #[test]
+    fn projection_lines_are_concatenated() {
+        let cnf = parse_dimacs("p cnf 4 1\nc p show 1 3 0\nc ind 4 0\n1 2 0\n".as_bytes()).unwrap();
+        assert_eq!(cnf.projection, Some(vec![1, 3, 4]));
+        assert_eq!(parse_dimacs("p cnf 4 1\n1 2 0\n".as_bytes()).unwrap().projection, None);
+        assert_eq!(error("p cnf 3 1\nc p show 1 0 2\n1 0\n"), (2, 1, "invalid projection line 'c p show 1 0 2'".to_string()));
+        assert_eq!(error("p cnf 3 1\n1 0\nc ind 2 7 0\n"), (3, 1, "variable 7 is above the 3 variables declared in the header".to_string()));
+    }
+}
@@ -0,0 +1,150 @@
+use malachite::Natural;
+use malachite::base::num::basic::traits::One;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::count::Count;
+use super::{InputVariable, Problem, VariableMap};
+
+fn find(parent: &mut [usize], x: usize) -> usize {
+    let mut root = x;
+    while parent[root] != root {
+        root = parent[root];
+    }
+    let mut x = x;
+    while parent[x] != root {
+        let next = parent[x];
+        parent[x] = root;
+        x = next;
+    }
+    root
+}
+
+impl Problem {
+
+    /// Splits the active clauses of the problem into connected components of its primal graph.
+    /// Each component is returned as a new problem, whose independent set is restricted to the
+    /// projected variables of the component. The variables of a component are numbered densely,
+    /// in the order of the problem, and its variable mapping gives their variables in the input
+    /// formula. The model count of the problem is the product of the counts of the components,
+    /// times the factor given by `free_factor` and the multiplication factor of the problem.
+    pub fn connected_components(&self) -> Vec<Problem> {
+        let mut parent = (0..self.number_var).collect::<Vec<usize>>();
+        for clause in self.iter_clauses() {
+            if let Some(first) = clause.first() {
+                let root = find(&mut parent, first.unsigned_abs() - 1);
+                for literal in clause.iter().skip(1) {
+                    let other = find(&mut parent, literal.unsigned_abs() - 1);
+                    if other != root {
+                        parent[other] = root;
+                    }
+                }
+            }
+        }
+        let mut component_of_root = FxHashMap::<usize, usize>::default();
+        let mut component_clauses: Vec<Vec<Vec<isize>>> = vec![];
+        for clause in self.iter_clauses() {
+            if let Some(first) = clause.first() {
+                let root = find(&mut parent, first.unsigned_abs() - 1);
+                let component = *component_of_root.entry(root).or_insert_with(|| {
+                    component_clauses.push(vec![]);
+                    component_clauses.len() - 1
+                });
+                component_clauses[component].push(clause.clone());
+            }
+        }
+        let mut component_projection: Vec<Vec<usize>> = vec![vec![]; component_clauses.len()];
+        for variable in self.iter_restricted_projection() {
+            let root = find(&mut parent, variable);
+            if let Some(&component) = component_of_root.get(&root) {
+                component_projection[component].push(variable);
+            }
+        }
+        // Component of each variable in a clause and its number in the component. The projected
+        // variables of a component are in its clauses.
+        let mut local = vec![None; self.number_var];
+        let mut component_variables: Vec<Vec<usize>> = vec![vec![]; component_clauses.len()];
+        for (variable, number) in local.iter_mut().enumerate() {
+            let root = find(&mut parent, variable);
+            if let Some(&component) = component_of_root.get(&root) && (!self.var_pos_occ[variable].is_empty() || !self.var_neg_occ[variable].is_empty()) {
+                *number = Some((component, component_variables[component].len()));
+                component_variables[component].push(variable);
+            }
+        }
+        let local_literal = |literal: isize| {
+            let (_, number) = local[literal.unsigned_abs() - 1].unwrap();
+            if literal < 0 { -(number as isize + 1) } else { number as isize + 1 }
+        };
+        component_clauses.into_iter().zip(component_projection).zip(component_variables).enumerate().map(|(index, ((clauses, independent_set), variables))| {
+            let clauses = clauses.into_iter().map(|clause| clause.into_iter().map(local_literal).collect()).collect();
+            let independent_set = independent_set.into_iter().map(|variable| local[variable].unwrap().1).collect();
+            let weights = self.weights.as_ref().map(|_| variables.iter().map(|&variable| self.weight(variable)).collect());
+            let mut component = Problem::build(variables.len(), clauses, independent_set, Count::one(), weights, self.projected);
+            let to_problem = (0..self.number_input_var()).map(|input| match self.input_variable_status(input) {
+                InputVariable::Literal(literal) => match local[literal.unsigned_abs() - 1] {
+                    Some((component, _)) if component == index => InputVariable::Literal(local_literal(literal)),
+                    _ => InputVariable::OtherComponent,
+                },
+                status => status,
+            }).collect();
+            let to_input = variables.iter().map(|&variable| self.input_variable(variable)).collect();
+            component.mapping = VariableMap::new(to_problem, to_input);
+            component
+        }).collect()
+    }
+
+    /// Returns the count contributed by the projected variables that are not in any active
+    /// clause: each free variable doubles the count (or multiplies the weighted count by the sum
+    /// of its literal weights), and a fixed variable multiplies the weighted count by the weight
+    /// of its literal. The factor is exact for unweighted problems.
+    pub fn free_factor(&self) -> Count {
+        let in_clauses = self.iter_clauses().flat_map(|clause| clause.iter().map(|l| l.unsigned_abs() - 1)).collect::<FxHashSet<usize>>();
+        let mut seen = FxHashSet::<usize>::default();
+        let mut free = 0;
+        let mut log10_factor = 0.0;
+        for variable in self.independent_set.iter().copied() {
+            let repr = self.literal_repr(variable as isize + 1).unsigned_abs() - 1;
+            if in_clauses.contains(&repr) || !seen.insert(repr) {
+                continue;
+            }
+            let (pos, neg) = self.weight(repr);
+            log10_factor += match self.value[repr] {
+                Some(true) => pos.log10(),
+                Some(false) => neg.log10(),
+                None => {
+                    free += 1;
+                    (pos + neg).log10()
+                },
+            };
+        }
+        if self.is_weighted() {
+            Count::from_log10(log10_factor)
+        } else {
+            Count::exact(Natural::ONE << free)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+
+    #[test]
+    fn clauses_are_split_by_shared_variables_and_numbered_densely() {
+        let cnf = parse_dimacs("p cnf 7 3\n1 -3 0\n2 5 0\n3 6 0\n".as_bytes()).unwrap();
+        let problem = Problem::from_cnf(cnf, (0..7).collect(), Count::one(), false);
+        let components = problem.connected_components();
+        assert_eq!(components.len(), 2);
+        let variables = components.iter().map(|component| (0..component.number_var()).map(|v| component.input_variable(v)).collect::<Vec<usize>>()).collect::<Vec<Vec<usize>>>();
+        assert_eq!(variables, vec![vec![0, 2, 5], vec![1, 4]]);
+        assert_eq!(components[0].iter_clauses().cloned().collect::<Vec<Vec<isize>>>(), vec![vec![1, -2], vec![2, 3]]);
+        assert_eq!(components[0].input_variable_status(5), InputVariable::Literal(3));
+        assert_eq!(components[0].input_variable_status(1), InputVariable::OtherComponent);
+        assert_eq!(components[1].iter_independent_set().collect::<Vec<usize>>(), vec![0, 1]);
+        // The free variables 4 and 7 are counted by the free factor
+        assert_eq!(problem.free_factor().log10(), 4f64.log10());
+        // Only the free variables of the independent set are counted
+        let cnf = parse_dimacs("p cnf 7 3\n1 -3 0\n2 5 0\n3 6 0\n".as_bytes()).unwrap();
+        assert_eq!(Problem::from_cnf(cnf, (0..6).collect(), Count::one(), false).free_factor().exact_value(), Some(&Natural::from(2u32)));
+    }
+}
@@ -0,0 +1,238 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use malachite::Natural;
+
+use crate::count::Count;
+use crate::supervisor::{Outcome, Supervised};
+use crate::tools;
+use crate::utils::ContentHash;
+
+/// Version of the format of the cache entries. Entries with another version are ignored.
+const FORMAT_VERSION: usize = 1;
+
+/// Header of the cache entries
+const HEADER: &str = "couaincre independent set";
+
+/// An independent set computed by arjun, with the factor by which the count projected on it must
+/// be multiplied
+pub struct IndependentSet {
+    /// Variables of the independent set, starting at 0
+    pub variables: Vec<usize>,
+    pub mult_factor: Count,
+}
+
+/// Returns the default cache directory: `$XDG_CACHE_HOME/couaincre`, or `~/.cache/couaincre`
+pub fn default_cache_dir() -> PathBuf {
+    match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("couaincre"),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache").join("couaincre"),
+            None => PathBuf::from(".couaincre-cache"),
+        },
+    }
+}
+
+/// Cache of the independent sets computed by arjun. An entry is keyed by a hash of the content
+/// of the input and of the version of arjun, so that an edited input or a new arjun is never
+/// served a stale independent set.
+pub struct IndependentSetCache {
+    directory: PathBuf,
+}
+
+/// Returns the key of the content of the input for the given version of arjun
+fn input_key(input: &Path, arjun_version: &str) -> std::io::Result<String> {
+    let mut hash = ContentHash::new();
+    let mut file = File::open(input)?;
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hash.write(&buffer[..read]);
+    }
+    hash.write(arjun_version.as_bytes());
+    Ok(hash.finish())
+}
+
+/// Runs arjun on the input, within the time limit if any. Returns None if it fails to compute an
+/// independent set.
+pub fn run_arjun(input: &Path, timeout: Option<u64>) -> Option<IndependentSet> {
+    let mut arjun = tools::command("arjun");
+    arjun.arg(input);
+    let mut supervised = Supervised::new("arjun", arjun);
+    if let Some(timeout) = timeout {
+        supervised = supervised.timeout(timeout);
+    }
+    let outcome = supervised.run(|_, arjun_out| {
+        let mut independent_set = None;
+        let mut mult_factor = Count::one();
+        for line in arjun_out.lines().rev() {
+            if line.starts_with("c p show") && independent_set.is_none() {
+                let variables = line.split_whitespace().skip(3).map(|s| s.parse::<usize>().ok()).collect::<Option<Vec<usize>>>()?;
+                independent_set = Some(variables.into_iter().filter(|&v| v > 0).map(|v| v - 1).collect::<Vec<usize>>());
+            }
+            if line.starts_with("c MUST MULTIPLY BY") {
+                mult_factor = Count::exact(line.split_whitespace().last()?.parse::<Natural>().ok()?);
+            }
+        }
+        independent_set.map(|variables| IndependentSet { variables, mult_factor })
+    });
+    match outcome {
+        Outcome::Solved(independent_set) => return Some(independent_set),
+        Outcome::Timeout => log::warn!("arjun did not finish within {} seconds", timeout.unwrap_or_default()),
+        Outcome::MemOut => log::warn!("arjun reached the memory limit"),
+        Outcome::Crashed(status) => log::warn!("arjun crashed ({})", status),
+        Outcome::ParseFailure => log::warn!("No independent set in the output of arjun"),
+        Outcome::LaunchFailure(_) | Outcome::Cancelled | Outcome::Unsupported => {},
+    }
+    None
+}
+
+impl IndependentSetCache {
+
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+        }
+    }
+
+    /// Returns the key of the input for the installed arjun
+    pub fn key(&self, input: &Path) -> std::io::Result<String> {
+        input_key(input, &tools::version("arjun").unwrap_or_default())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.is", key))
+    }
+
+    /// Returns the independent set stored for the key. Entries that can not be read are ignored
+    /// with a warning.
+    pub fn get(&self, key: &str) -> Option<IndependentSet> {
+        let path = self.entry_path(key);
+        let content = std::fs::read_to_string(&path).ok()?;
+        let entry = parse_entry(&content);
+        if entry.is_none() {
+            log::warn!("Ignoring invalid independent set cache entry {}", path.display());
+        }
+        entry.map(|(_, independent_set)| independent_set)
+    }
+
+    /// Stores the independent set for the key. The input path is only kept for the listing of the
+    /// entries.
+    pub fn store(&self, key: &str, input: &Path, independent_set: &IndependentSet) {
+        let mut content = format!("{} {}\n", HEADER, FORMAT_VERSION);
+        content.push_str(&format!("input {}\n", input.display()));
+        content.push_str(&format!("c p show {} 0\n", independent_set.variables.iter().map(|v| (v + 1).to_string()).collect::<Vec<String>>().join(" ")));
+        match independent_set.mult_factor.exact_value() {
+            Some(factor) => content.push_str(&format!("mult {}\n", factor)),
+            None => content.push_str(&format!("log10 mult {}\n", independent_set.mult_factor.log10())),
+        }
+        let path = self.entry_path(key);
+        let temporary = self.directory.join(format!("{}.{}.tmp", key, std::process::id()));
+        let written = std::fs::create_dir_all(&self.directory)
+            .and_then(|_| std::fs::write(&temporary, content))
+            .and_then(|_| std::fs::rename(&temporary, &path));
+        if let Err(error) = written {
+            log::warn!("Can not store the independent set in {}: {}", path.display(), error);
+            let _ = std::fs::remove_file(&temporary);
+        }
+    }
+
+    /// Returns the entries of the cache
+    fn entries(&self) -> Vec<PathBuf> {
+        let Ok(read_dir) = std::fs::read_dir(&self.directory) else { return vec![]; };
+        let mut entries = read_dir.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "is"))
+            .collect::<Vec<PathBuf>>();
+        entries.sort();
+        entries
+    }
+
+    /// Prints the entries of the cache on the standard output
+    pub fn list(&self) {
+        println!("Independent set cache in {}", self.directory.display());
+        for path in self.entries() {
+            let key = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+            match std::fs::read_to_string(&path).ok().as_deref().and_then(parse_entry) {
+                Some((input, independent_set)) => println!("{} {} variables, factor {}, from {}", key, independent_set.variables.len(), independent_set.mult_factor, input),
+                None => println!("{} invalid or outdated entry", key),
+            }
+        }
+    }
+
+    /// Removes all the entries of the cache. Returns the number of removed entries.
+    pub fn clear(&self) -> usize {
+        self.entries().into_iter().filter(|path| std::fs::remove_file(path).is_ok()).count()
+    }
+}
+
+/// Parses a cache entry. Returns the input from which it was computed and the independent set.
+fn parse_entry(content: &str) -> Option<(String, IndependentSet)> {
+    let mut lines = content.lines();
+    let version = lines.next()?.strip_prefix(HEADER)?.trim().parse::<usize>().ok()?;
+    if version != FORMAT_VERSION {
+        return None;
+    }
+    let mut input = None;
+    let mut variables = None;
+    let mut mult_factor = None;
+    for line in lines {
+        if let Some(path) = line.strip_prefix("input ") {
+            input = Some(path.to_string());
+        } else if let Some(show) = line.strip_prefix("c p show") {
+            let show = show.split_whitespace().map(|v| v.parse::<usize>().ok()).collect::<Option<Vec<usize>>>()?;
+            variables = Some(show.into_iter().filter(|&v| v > 0).map(|v| v - 1).collect::<Vec<usize>>());
+        } else if let Some(factor) = line.strip_prefix("mult ") {
+            mult_factor = Some(Count::exact(factor.trim().parse::<Natural>().ok()?));
+        } else if let Some(factor) = line.strip_prefix("log10 mult ") {
+            mult_factor = Some(Count::from_log10(factor.trim().parse::<f64>().ok()?));
+        } else {
+            return None;
+        }
+    }
+    Some((input.unwrap_or_default(), IndependentSet {
+        variables: variables?,
+        mult_factor: mult_factor?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_change_with_the_input_and_the_arjun_version() {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-is-key", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (first, second) = (directory.join("first.cnf"), directory.join("second.cnf"));
+        std::fs::write(&first, "p cnf 2 1\n1 2 0\n").unwrap();
+        std::fs::write(&second, "p cnf 2 1\n1 -2 0\n").unwrap();
+        let key = input_key(&first, "arjun 2.5.4").unwrap();
+        assert_eq!(input_key(&first, "arjun 2.5.4").unwrap(), key);
+        assert_ne!(input_key(&first, "arjun 2.5.5").unwrap(), key);
+        assert_ne!(input_key(&second, "arjun 2.5.4").unwrap(), key);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn entries_are_read_back() {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-is-entries", std::process::id()));
+        let cache = IndependentSetCache::new(directory.clone());
+        let exact = IndependentSet { variables: vec![0, 4, 7], mult_factor: Count::exact(Natural::from(6u32)) };
+        cache.store("exact", Path::new("exact.cnf"), &exact);
+        cache.store("estimate", Path::new("estimate.cnf"), &IndependentSet { variables: vec![], mult_factor: Count::from_log10(40.5) });
+        let read = cache.get("exact").unwrap();
+        assert_eq!(read.variables, exact.variables);
+        assert_eq!(read.mult_factor.exact_value(), Some(&Natural::from(6u32)));
+        let read = cache.get("estimate").unwrap();
+        assert!(read.variables.is_empty() && read.mult_factor.log10() == 40.5);
+        std::fs::write(directory.join("outdated.is"), format!("{} {}\nc p show 1 0\nmult 1\n", HEADER, FORMAT_VERSION + 1)).unwrap();
+        assert!(cache.get("outdated").is_none());
+        assert!(cache.get("missing").is_none());
+        assert_eq!(cache.clear(), 3);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
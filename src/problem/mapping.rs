@@ -0,0 +1,59 @@
+/// What a variable of the input formula became in the problem
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InputVariable {
+    /// The variable is equal to this literal (in DIMACS numbering) of the problem
+    Literal(isize),
+    /// The variable has the same value in every model
+    Fixed(bool),
+    /// The variable has been eliminated by the pre-processing
+    Eliminated,
+    /// The variable is in another connected component of the problem
+    OtherComponent,
+}
+
+/// Mapping between the variables of the input formula and the variables of the problem
+#[derive(Clone)]
+pub struct VariableMap {
+    to_problem: Vec<InputVariable>,
+    to_input: Vec<usize>,
+}
+
+impl VariableMap {
+
+    /// Mapping given by what each variable of the input became and by the variable of the input
+    /// of each variable of the problem
+    pub fn new(to_problem: Vec<InputVariable>, to_input: Vec<usize>) -> Self {
+        Self {
+            to_problem,
+            to_input,
+        }
+    }
+
+    /// Mapping for a problem that uses the numbering of the input
+    pub fn identity(number_var: usize) -> Self {
+        Self {
+            to_problem: (1..=number_var as isize).map(InputVariable::Literal).collect(),
+            to_input: (0..number_var).collect(),
+        }
+    }
+
+    /// Returns the number of variables in the input formula
+    pub fn number_input_var(&self) -> usize {
+        self.to_problem.len()
+    }
+
+    /// Returns what the variable of the input became in the problem
+    pub fn to_problem(&self, variable: usize) -> InputVariable {
+        self.to_problem[variable]
+    }
+
+    /// Returns the variable of the input corresponding to the variable of the problem
+    pub fn to_input(&self, variable: usize) -> usize {
+        self.to_input[variable]
+    }
+
+    /// Marks the variable of the input as eliminated
+    pub fn eliminate(&mut self, variable: usize) {
+        self.to_problem[variable] = InputVariable::Eliminated;
+    }
+}
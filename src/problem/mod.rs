@@ -0,0 +1,954 @@
+use rustc_hash::FxHashSet;
+use std::process::exit;
+
+use crate::{Args, CTRL};
+use crate::parser::{Cnf, parse_dimacs};
+use crate::input::{PlainInput, read_input};
+use crate::count::Count;
+use crate::supervisor::{Outcome, Supervised};
+use crate::tools;
+
+mod simplify;
+mod components;
+mod preprocess;
+mod mapping;
+mod independent_set;
+mod sat;
+
+pub use preprocess::Preprocessor;
+pub use mapping::{InputVariable, VariableMap};
+pub use independent_set::{IndependentSetCache, default_cache_dir};
+pub use sat::SatSolver;
+use independent_set::run_arjun;
+use sat::Satisfiability;
+
+/// Modification of the problem done by a substitution, stored on the trail so that it can be
+/// undone.
+#[derive(Clone)]
+enum TrailEntry {
+    /// The clause has been deactivated (it became a tautology)
+    Deactivate(usize),
+    /// The literal has been removed from the clause, with `swap_remove`, at the given index
+    RemoveLiteral(usize, usize, isize),
+    /// The literal at the given index in the clause has been replaced
+    ReplaceLiteral(usize, usize, isize),
+    /// The variable has been substituted by a literal
+    Substitute(usize),
+    /// The variable has been assigned a value
+    Assign(usize),
+    /// The weights of the variable before they were modified
+    Weights(usize, (f64, f64)),
+    /// A variable has been restricted to be equal to its negation, or a clause became empty
+    Contradiction,
+    /// A clause has been added at the end of the clauses
+    AddClause,
+}
+
+#[derive(Clone)]
+pub struct Problem {
+    number_var: usize,
+    clauses: Vec<Vec<isize>>,
+    active: Vec<bool>,
+    var_pos_occ: Vec<FxHashSet<usize>>,
+    var_neg_occ: Vec<FxHashSet<usize>>,
+    independent_set: Vec<usize>,
+    /// Factor by which the count of the problem must be multiplied, given by arjun
+    mult_factor: Count,
+    /// Weights of the positive and negative literals of each variable, if the formula is weighted
+    weights: Option<Vec<(f64, f64)>>,
+    /// True if the count is projected on a set of variables declared in the input
+    projected: bool,
+    /// For each variable, the literal that replaces it (itself if it has not been substituted)
+    repr: Vec<isize>,
+    /// Modifications done by the substitutions, in order
+    trail: Vec<TrailEntry>,
+    /// Start of each restriction level in the trail, with the clauses modified since the last
+    /// simplification when the level was opened
+    levels: Vec<(usize, FxHashSet<usize>)>,
+    contradictions: usize,
+    /// Value of the variables fixed by the simplifications
+    value: Vec<Option<bool>>,
+    /// Clauses modified since the last simplification
+    touched: FxHashSet<usize>,
+    /// Mapping between the variables of the input formula and the variables of the problem
+    mapping: VariableMap,
+    /// A model of the input formula found by the satisfiability check, if it proved the formula
+    /// SAT. It is None when the check did not decide within its time limit.
+    witness: Option<Vec<bool>>,
+}
+
+impl Problem {
+
+    pub fn new(args: &Args) -> Self {
+        let (input, plain_input) = match read_input(args.input()) {
+            Ok(input) => input,
+            Err(error) => {
+                log::error!("Can not parse {}: {}", args.input().display(), error);
+                exit(1);
+            },
+        };
+        let number_var = input.number_var;
+        log::info!("CNF file with {} variables and {} clauses before preprocess", number_var, input.clauses.len());
+        // We launch a SAT solver to verify that the formula is SAT.
+        log::trace!("Checking satisfiability of the formula");
+        let witness = match args.sat_solver.check(&input, plain_input.path(), args.preproc_timeout as u64) {
+            Satisfiability::Sat(model) => {
+                log::info!("Formula is SAT. {} seconds elapsed since start", CTRL.elapsed());
+                Some(model)
+            },
+            Satisfiability::Unsat => {
+                log::info!("Formula is UNSAT");
+                return Self {
+                    number_var: 0,
+                    clauses: vec![],
+                    active: vec![],
+                    var_pos_occ: vec![],
+                    var_neg_occ: vec![],
+                    independent_set: vec![],
+                    mult_factor: Count::one(),
+                    weights: None,
+                    projected: false,
+                    repr: vec![],
+                    trail: vec![],
+                    levels: vec![],
+                    contradictions: 0,
+                    value: vec![],
+                    touched: FxHashSet::default(),
+                    mapping: VariableMap::identity(number_var),
+                    witness: None,
+                };
+            },
+            Satisfiability::Unknown => {
+                log::warn!("Satisfiability of the formula not decided within {} seconds", args.preproc_timeout);
+                None
+            },
+        };
+        let mut problem = Self::preprocessed(args, input, plain_input, witness.as_deref());
+        problem.witness = witness;
+        problem
+    }
+
+    /// Builds the problem from the input, with its independent set, and pre-processes it. The
+    /// witness, a model of the input, is used to check the numbering of the B+E output.
+    fn preprocessed(args: &Args, input: Cnf, plain_input: PlainInput, witness: Option<&[bool]>) -> Self {
+        let number_var = input.number_var;
+        let projection = input.projection.as_ref().map(|projection| {
+            let mut projection = projection.iter().map(|v| v - 1).collect::<Vec<usize>>();
+            projection.sort_unstable();
+            projection.dedup();
+            projection
+        });
+        let projected = projection.is_some();
+        if let Some(projection) = projection.as_ref() {
+            log::info!("Input declares a projection set of {} variables", projection.len());
+        }
+        if !input.weights.is_empty() {
+            // B+E and arjun are not aware of the weights. The independent set would project away
+            // weighted variables and the eliminated variables would lose their weights.
+            log::info!("Weighted formula with {} literal weights, bypassing independent set computation and B+E pre-processing", input.weights.len());
+            let independent_set = projection.unwrap_or_else(|| (0..number_var).collect());
+            let mut problem = Self::from_cnf(input, independent_set, Count::one(), projected);
+            if args.preprocessor == Preprocessor::Native {
+                problem.preprocess(args.preproc_timeout as u64);
+            }
+            return problem;
+        }
+        let independent_set: Vec<usize>;
+        let mut mult_factor = Count::one();
+        if let Some(projection) = projection.as_ref().filter(|_| !args.minimize_projection) {
+            // The projection set is part of the problem, it is used as is. Arjun only runs to
+            // shrink it when asked.
+            independent_set = projection.clone();
+        } else if !tools::is_available("arjun") {
+            log::warn!("arjun is not available, using {} as independent set", if projected { "the projection set" } else { "all the variables" });
+            independent_set = projection.clone().unwrap_or_else(|| (0..number_var).collect());
+        } else {
+            // The independent set can take some time to compute, it is cached between runs
+            let cache = (!args.no_cache).then(|| IndependentSetCache::new(args.cache_dir()));
+            let key = cache.as_ref().and_then(|cache| match cache.key(plain_input.path()) {
+                Ok(key) => Some(key),
+                Err(error) => {
+                    log::warn!("Can not compute the cache key of the input: {}", error);
+                    None
+                },
+            });
+            let cached = match (cache.as_ref(), key.as_ref()) {
+                (Some(cache), Some(key)) if !args.refresh_cache => cache.get(key),
+                _ => None,
+            };
+            let computed = match cached {
+                Some(cached) => {
+                    log::info!("Independent set found in the cache");
+                    Some(cached)
+                },
+                None => {
+                    log::info!("Computing an independent set using arjun");
+                    let computed = run_arjun(plain_input.path(), args.arjun_timeout);
+                    if let (Some(computed), Some(cache), Some(key)) = (computed.as_ref(), cache.as_ref(), key.as_ref()) {
+                        cache.store(key, args.input(), computed);
+                    }
+                    computed
+                },
+            };
+            match computed {
+                Some(computed) => {
+                    independent_set = computed.variables;
+                    mult_factor = computed.mult_factor;
+                },
+                None => {
+                    // The whole projection (or all the variables) is an independent set
+                    log::warn!("arjun did not compute an independent set, using {}", if projected { "the projection set" } else { "all the variables" });
+                    independent_set = projection.clone().unwrap_or_else(|| (0..number_var).collect());
+                },
+            };
+        }
+        log::trace!("Independent set size : {} ({:.2}% of variables set size). Multiplication factor is {}", independent_set.len(), ((independent_set.len() as f64) / (number_var as f64)) * 100.0, mult_factor);
+        if args.preprocessor == Preprocessor::Native {
+            let mut problem = Self::from_cnf(input, independent_set, mult_factor, projected);
+            problem.preprocess(args.preproc_timeout as u64);
+            return problem;
+        }
+        if projected {
+            // B+E does not know about the projection set and may eliminate projected variables,
+            // which changes the projected count.
+            log::info!("Projected formula, bypassing B+E pre-processing");
+            return Self::from_cnf(input, independent_set, mult_factor, projected);
+        }
+        // First, we pre-process the formula using the B+E tool available at https://www.cril.univ-artois.fr/kc/bpe2.html
+        // This tool takes a CNF formula in DIMACS file as input and return a new formula in DIMACS
+        // format.
+        log::trace!("Launche B+E pre-processing with {} seconds time limit", args.preproc_timeout);
+        let mut bpe = tools::command("bpe");
+        bpe.arg(plain_input.path());
+        let bpe_result = Supervised::new("B+E", bpe).timeout(args.preproc_timeout as u64).run(|_, bpe_out| {
+            if bpe_out.ends_with("s UNSATISFIABLE\n") {
+                return None;
+            }
+            parse_dimacs(bpe_out.as_bytes()).inspect_err(|error| log::warn!("Can not parse the output of B+E ({})", error)).ok()
+        });
+        log::trace!("Pre-processing finished. {} seconds elapsed since start", CTRL.elapsed());
+        let preprocessed = match bpe_result {
+            Outcome::Solved(cnf) => {
+                log::trace!("B+E found a pre-processed formula");
+                Some(cnf)
+            },
+            Outcome::Timeout => {
+                log::info!("B+E timed out. Bypassing pre-processing");
+                None
+            },
+            Outcome::MemOut => {
+                log::warn!("B+E reached the memory limit. Bypassing pre-processing");
+                None
+            },
+            Outcome::Crashed(status) => {
+                log::warn!("B+E crashed ({}). Bypassing pre-processing", status);
+                None
+            },
+            Outcome::ParseFailure => {
+                log::warn!("No pre-processed formula in the output of B+E. Bypassing pre-processing");
+                None
+            },
+            Outcome::LaunchFailure(_) => {
+                log::warn!("B+E could not be launched. Bypassing pre-processing");
+                None
+            },
+            Outcome::Cancelled | Outcome::Unsupported => unreachable!(),
+        };
+        // B+E keeps the numbering of the variables it does not eliminate, but only declares the
+        // variables up to the largest remaining one: its formula is read with the variables of
+        // the input. Since the clauses of B+E are implied by the input, a model of the input must
+        // satisfy them, otherwise the variables have been renumbered in an unknown way.
+        let preprocessed = preprocessed.filter(|cnf| {
+            if cnf.number_var > number_var {
+                log::warn!("B+E declares {} variables instead of at most {}. Bypassing pre-processing", cnf.number_var, number_var);
+                return false;
+            }
+            let satisfied = |clause: &Vec<isize>| clause.iter().any(|&l| witness.is_none_or(|witness| witness[l.unsigned_abs() - 1] == (l > 0)));
+            if !cnf.clauses.iter().all(satisfied) {
+                log::warn!("The B+E formula does not follow the numbering of the input. Bypassing pre-processing");
+                return false;
+            }
+            true
+        });
+        // If BPE did not find any pre-processing, we use the input formula
+        let Some(cnf) = preprocessed else {
+            log::info!("After preprocess : {} variables and {} clauses", input.number_var, input.clauses.len());
+            return Self::from_cnf(input, independent_set, mult_factor, projected);
+        };
+        Self::from_preprocessed(&input, cnf, independent_set, mult_factor, projected)
+    }
+
+    /// Builds the problem from the formula of B+E, in the numbering of the input. The variables
+    /// eliminated by B+E (the ones of the input that no longer appear in a clause) are removed and
+    /// the remaining ones are numbered densely, the mapping of the problem giving their variable
+    /// in the input.
+    pub fn from_preprocessed(input: &Cnf, cnf: Cnf, mut independent_set: Vec<usize>, mult_factor: Count, projected: bool) -> Self {
+        let number_var = input.number_var;
+        let mut remaining = vec![false; number_var];
+        for literal in cnf.clauses.iter().flatten() {
+            remaining[literal.unsigned_abs() - 1] = true;
+        }
+        let mut eliminated = vec![false; number_var];
+        for literal in input.clauses.iter().flatten() {
+            eliminated[literal.unsigned_abs() - 1] = !remaining[literal.unsigned_abs() - 1];
+        }
+        // The eliminated variables are defined by the other ones, and the B+E formula has as many
+        // models as the input. If variables of the independent set have been eliminated, the
+        // other ones may no longer determine the models, which are then counted on all the
+        // remaining variables.
+        if independent_set.iter().any(|&variable| eliminated[variable]) {
+            log::info!("B+E eliminated variables of the independent set, counting on all the variables of the pre-processed formula");
+            let in_independent_set = independent_set.iter().copied().collect::<FxHashSet<usize>>();
+            independent_set = (0..number_var).filter(|&variable| remaining[variable] || (in_independent_set.contains(&variable) && !eliminated[variable])).collect();
+        }
+        let to_input = (0..number_var).filter(|&variable| !eliminated[variable]).collect::<Vec<usize>>();
+        let mut to_problem = vec![InputVariable::Eliminated; number_var];
+        for (variable, &input_variable) in to_input.iter().enumerate() {
+            to_problem[input_variable] = InputVariable::Literal(variable as isize + 1);
+        }
+        let renumber = |literal: isize| match to_problem[literal.unsigned_abs() - 1] {
+            InputVariable::Literal(variable) => if literal < 0 { -variable } else { variable },
+            _ => unreachable!(),
+        };
+        let clauses = cnf.clauses.iter().map(|clause| clause.iter().map(|&l| renumber(l)).collect()).collect::<Vec<Vec<isize>>>();
+        let independent_set = independent_set.into_iter().map(|variable| renumber(variable as isize + 1) as usize - 1).collect();
+        log::info!("After preprocess : {} variables and {} clauses", to_input.len(), clauses.len());
+        let mut problem = Self::build(to_input.len(), clauses, independent_set, mult_factor, None, projected);
+        problem.mapping = VariableMap::new(to_problem, to_input);
+        problem
+    }
+
+    pub fn from_cnf(cnf: Cnf, independent_set: Vec<usize>, mult_factor: Count, projected: bool) -> Self {
+        let Cnf { number_var: number_var_after_preproc, clauses, weights: literal_weights, .. } = cnf;
+        log::info!("Number of unary clauses: {}", clauses.iter().filter(|cls| cls.len() == 1).count());
+        log::info!("Number of binary clauses: {}", clauses.iter().filter(|cls| cls.len() == 2).count());
+        log::info!("Number of ternary of more clauses: {}", clauses.iter().filter(|cls| cls.len() > 2).count());
+
+        let weights = if literal_weights.is_empty() {
+            None
+        } else {
+            let mut weights = vec![(1.0, 1.0); number_var_after_preproc];
+            for (literal, weight) in literal_weights {
+                if literal < 0 {
+                    weights[literal.unsigned_abs() - 1].1 = weight;
+                } else {
+                    weights[literal.unsigned_abs() - 1].0 = weight;
+                }
+            }
+            Some(weights)
+        };
+        Self::build(number_var_after_preproc, clauses, independent_set, mult_factor, weights, projected)
+    }
+
+    fn build(number_var: usize, mut clauses: Vec<Vec<isize>>, independent_set: Vec<usize>, mult_factor: Count, mut weights: Option<Vec<(f64, f64)>>, projected: bool) -> Self {
+        // In a projected count, only the weights of the projected variables are taken into
+        // account. The other ones are dropped so that the substitutions do not move them to the
+        // projected variables.
+        if let Some(weights) = weights.as_mut().filter(|_| projected) {
+            let mut kept = vec![(1.0, 1.0); number_var];
+            for &variable in independent_set.iter() {
+                kept[variable] = weights[variable];
+            }
+            *weights = kept;
+        }
+        // The substitutions expect each literal at most once in a clause. Tautologies do not
+        // constrain the models and start deactivated.
+        let mut active = vec![true; clauses.len()];
+        for (clause, active) in clauses.iter_mut().zip(active.iter_mut()) {
+            let mut seen = FxHashSet::default();
+            clause.retain(|&literal| seen.insert(literal));
+            *active = !clause.iter().any(|literal| seen.contains(&-literal));
+        }
+        let mut var_pos_occ = vec![FxHashSet::default(); number_var];
+        let mut var_neg_occ = vec![FxHashSet::default(); number_var];
+        for (clause_id, clause) in clauses.iter().enumerate().filter(|&(clause_id, _)| active[clause_id]) {
+            for literal in clause.iter().copied() {
+                if literal < 0 {
+                    var_neg_occ[literal.unsigned_abs() - 1].insert(clause_id);
+                } else {
+                    var_pos_occ[literal.unsigned_abs() - 1].insert(clause_id);
+                }
+            }
+        }
+        Self {
+            number_var,
+            clauses,
+            active,
+            var_pos_occ,
+            var_neg_occ,
+            independent_set,
+            mult_factor,
+            weights,
+            projected,
+            repr: (1..=number_var as isize).collect(),
+            trail: vec![],
+            levels: vec![],
+            contradictions: 0,
+            value: vec![None; number_var],
+            touched: FxHashSet::default(),
+            mapping: VariableMap::identity(number_var),
+            witness: None,
+        }
+    }
+
+    /// Returns true if the problem is empty
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+
+    /// Returns the number of variable in the problem
+    pub fn number_var(&self) -> usize {
+        self.number_var
+    }
+
+    /// Returns the number of clauses in the problem
+    pub fn number_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+
+    /// Iterates on the clauses of the problem
+    pub fn iter_clauses(&self) -> impl Iterator<Item = &Vec<isize>> {
+        self.clauses.iter().enumerate().filter(|(index, _)| self.active[*index]).map(|c| c.1)
+    }
+
+    /// Iterates on the clauses of the problem in DIMACS format
+    pub fn iter_clauses_dimacs(&self) -> impl Iterator<Item = String> {
+        self.iter_clauses().map(|clause| format!("{} 0", clause.iter().map(|l| l.to_string()).collect::<Vec<String>>().join(" ")))
+    }
+
+    /// Returns the active clauses in which the variable appears positively
+    pub fn positive_occurences(&self, variable: usize) -> &FxHashSet<usize> {
+        &self.var_pos_occ[variable]
+    }
+
+    /// Returns the active clauses in which the variable appears negatively
+    pub fn negative_occurences(&self, variable: usize) -> &FxHashSet<usize> {
+        &self.var_neg_occ[variable]
+    }
+
+    pub fn is_clause_active(&self, index: usize) -> bool {
+        self.active[index]
+    }
+
+    pub fn clause_at(&self, index: usize) -> &Vec<isize> {
+        &self.clauses[index]
+    }
+
+    /// Returns true if the count is projected on a set of variables declared in the input
+    pub fn is_projected(&self) -> bool {
+        self.projected
+    }
+
+    /// Returns a description of the count computed for the problem, used when reporting it
+    pub fn count_description(&self) -> &'static str {
+        match (self.projected, self.is_weighted()) {
+            (false, false) => "model count",
+            (true, false) => "projected model count",
+            (false, true) => "weighted model count",
+            (true, true) => "projected weighted model count",
+        }
+    }
+
+    /// Returns true if the literals of the problem are weighted
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// Returns the weights of the positive and negative literals of the variable. Unweighted
+    /// literals have weight 1.
+    pub fn weight(&self, variable: usize) -> (f64, f64) {
+        match &self.weights {
+            Some(weights) => weights[variable],
+            None => (1.0, 1.0),
+        }
+    }
+
+    /// Returns the literal (in DIMACS numbering) that currently replaces the given literal, after
+    /// the substitutions applied by `make_equal` and `make_not_equal`
+    pub fn literal_repr(&self, literal: isize) -> isize {
+        let mut literal = literal;
+        loop {
+            let repr = self.repr[literal.unsigned_abs() - 1];
+            if repr.unsigned_abs() == literal.unsigned_abs() {
+                return literal;
+            }
+            literal = if literal < 0 { -repr } else { repr };
+        }
+    }
+
+    /// Returns true if the substitutions or the simplifications made the problem trivially UNSAT
+    /// (e.g., a variable was restricted to be equal to its negation)
+    pub fn is_contradictory(&self) -> bool {
+        self.contradictions > 0
+    }
+
+    /// Returns the number of restriction levels currently applied on the problem
+    pub fn restriction_level(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Opens a new restriction level. All the substitutions done afterwards are undone by the next
+    /// call to `backtrack`.
+    pub fn new_level(&mut self) {
+        self.levels.push((self.trail.len(), self.touched.clone()));
+    }
+
+    /// Returns the current position in the trail, from which `clauses_modified_since` finds the
+    /// clauses modified afterwards
+    pub fn trail_position(&self) -> usize {
+        self.trail.len()
+    }
+
+    /// Returns the clauses modified since the trail position: the ones whose literals changed, the
+    /// deactivated ones and the added ones. The problem must not have been backtracked below the
+    /// position.
+    pub fn clauses_modified_since(&self, position: usize) -> FxHashSet<usize> {
+        let mut modified = FxHashSet::default();
+        let mut added = 0;
+        for entry in self.trail[position..].iter() {
+            match *entry {
+                TrailEntry::Deactivate(clause_id) | TrailEntry::RemoveLiteral(clause_id, _, _) | TrailEntry::ReplaceLiteral(clause_id, _, _) => {
+                    modified.insert(clause_id);
+                },
+                TrailEntry::AddClause => added += 1,
+                _ => {},
+            }
+        }
+        // Without backtracking, the added clauses are the last ones
+        modified.extend(self.clauses.len() - added..self.clauses.len());
+        modified
+    }
+
+    /// Undoes all the substitutions done since the last call to `new_level`
+    pub fn backtrack(&mut self) {
+        let (start, touched) = self.levels.pop().expect("backtrack without restriction level");
+        self.touched = touched;
+        while self.trail.len() > start {
+            match self.trail.pop().unwrap() {
+                TrailEntry::Deactivate(clause_id) => {
+                    self.active[clause_id] = true;
+                    for literal in self.clauses[clause_id].clone() {
+                        self.occurences_mut(literal).insert(clause_id);
+                    }
+                },
+                TrailEntry::RemoveLiteral(clause_id, index, literal) => {
+                    let clause = &mut self.clauses[clause_id];
+                    clause.push(literal);
+                    let last = clause.len() - 1;
+                    clause.swap(index, last);
+                    self.occurences_mut(literal).insert(clause_id);
+                },
+                TrailEntry::ReplaceLiteral(clause_id, index, literal) => {
+                    let replaced_by = self.clauses[clause_id][index];
+                    self.clauses[clause_id][index] = literal;
+                    if !self.clauses[clause_id].contains(&replaced_by) {
+                        self.occurences_mut(replaced_by).remove(&clause_id);
+                    }
+                    self.occurences_mut(literal).insert(clause_id);
+                },
+                TrailEntry::Substitute(variable) => {
+                    self.repr[variable] = variable as isize + 1;
+                },
+                TrailEntry::Assign(variable) => {
+                    self.value[variable] = None;
+                },
+                TrailEntry::Weights(variable, weight) => {
+                    self.weights.as_mut().unwrap()[variable] = weight;
+                },
+                TrailEntry::Contradiction => {
+                    self.contradictions -= 1;
+                },
+                TrailEntry::AddClause => {
+                    let clause_id = self.clauses.len() - 1;
+                    if self.active[clause_id] {
+                        for literal in self.clauses[clause_id].clone() {
+                            self.occurences_mut(literal).remove(&clause_id);
+                        }
+                    }
+                    self.clauses.pop();
+                    self.active.pop();
+                },
+            }
+        }
+    }
+
+    fn occurences_mut(&mut self, literal: isize) -> &mut FxHashSet<usize> {
+        if literal < 0 {
+            &mut self.var_neg_occ[literal.unsigned_abs() - 1]
+        } else {
+            &mut self.var_pos_occ[literal.unsigned_abs() - 1]
+        }
+    }
+
+    fn contradiction(&mut self) {
+        self.contradictions += 1;
+        self.trail.push(TrailEntry::Contradiction);
+    }
+
+    fn add_clause(&mut self, clause: Vec<isize>) {
+        let clause_id = self.clauses.len();
+        for literal in clause.iter().copied() {
+            self.occurences_mut(literal).insert(clause_id);
+        }
+        self.clauses.push(clause);
+        self.active.push(true);
+        self.touched.insert(clause_id);
+        self.trail.push(TrailEntry::AddClause);
+    }
+
+    fn deactivate(&mut self, clause_id: usize) {
+        self.active[clause_id] = false;
+        for literal in self.clauses[clause_id].clone() {
+            self.occurences_mut(literal).remove(&clause_id);
+        }
+        self.trail.push(TrailEntry::Deactivate(clause_id));
+    }
+
+    /// Replaces every occurence of `from` by `to` in the clause. The clause is deactivated if it
+    /// becomes a tautology and the literal is removed if `to` is already in the clause.
+    fn substitute_in_clause(&mut self, clause_id: usize, from: isize, to: isize) {
+        while let Some(index) = self.clauses[clause_id].iter().position(|&l| l == from) {
+            if self.clauses[clause_id].contains(&-to) {
+                self.deactivate(clause_id);
+                return;
+            }
+            if self.clauses[clause_id].contains(&to) {
+                self.clauses[clause_id].swap_remove(index);
+                self.trail.push(TrailEntry::RemoveLiteral(clause_id, index, from));
+            } else {
+                self.clauses[clause_id][index] = to;
+                self.occurences_mut(to).insert(clause_id);
+                self.trail.push(TrailEntry::ReplaceLiteral(clause_id, index, from));
+            }
+        }
+        self.occurences_mut(from).remove(&clause_id);
+        self.touched.insert(clause_id);
+    }
+
+    /// Sets the literal to true. The clauses it satisfies are deactivated and its negation is
+    /// removed from the other clauses. The literal must be on a non-substituted, unassigned
+    /// variable.
+    fn assign(&mut self, literal: isize) {
+        let variable = literal.unsigned_abs() - 1;
+        self.value[variable] = Some(literal > 0);
+        self.trail.push(TrailEntry::Assign(variable));
+        for clause_id in self.occurences_mut(literal).iter().copied().collect::<Vec<usize>>() {
+            self.deactivate(clause_id);
+        }
+        for clause_id in self.occurences_mut(-literal).iter().copied().collect::<Vec<usize>>() {
+            if self.clauses[clause_id].len() == 1 {
+                self.contradiction();
+                continue;
+            }
+            let index = self.clauses[clause_id].iter().position(|&l| l == -literal).unwrap();
+            self.clauses[clause_id].swap_remove(index);
+            self.trail.push(TrailEntry::RemoveLiteral(clause_id, index, -literal));
+            self.occurences_mut(-literal).remove(&clause_id);
+            self.touched.insert(clause_id);
+        }
+    }
+
+    /// Returns the value of the literal if its variable, or the variable replacing it, has been
+    /// fixed by the simplifications
+    pub fn literal_value(&self, literal: isize) -> Option<bool> {
+        let literal = self.literal_repr(literal);
+        self.value[literal.unsigned_abs() - 1].map(|value| value == (literal > 0))
+    }
+
+    /// Iterates on the literals fixed by the simplifications
+    pub fn iter_fixed_literals(&self) -> impl Iterator<Item = isize> {
+        self.value.iter().enumerate().filter_map(|(variable, value)| value.map(|value| if value { variable as isize + 1 } else { -(variable as isize + 1) }))
+    }
+
+    /// Iterates on the projected variables of the restricted problem, that is the variables
+    /// replacing the variables of the independent set, without the fixed variables and without
+    /// duplicates.
+    pub fn iter_restricted_projection(&self) -> impl Iterator<Item = usize> {
+        let mut seen = FxHashSet::<usize>::default();
+        self.independent_set.iter().map(|&v| self.literal_repr(v as isize + 1).unsigned_abs() - 1).filter(|&v| self.value[v].is_none()).filter(move |&v| seen.insert(v))
+    }
+
+    /// Replaces the variable by the literal in every active clause. The variable must not have
+    /// been substituted yet and the literal must be on a non-substituted variable.
+    fn substitute(&mut self, variable: usize, literal: isize) {
+        let positive = variable as isize + 1;
+        for clause_id in self.var_pos_occ[variable].iter().copied().collect::<Vec<usize>>() {
+            if self.active[clause_id] {
+                self.substitute_in_clause(clause_id, positive, literal);
+            }
+        }
+        for clause_id in self.var_neg_occ[variable].iter().copied().collect::<Vec<usize>>() {
+            if self.active[clause_id] {
+                self.substitute_in_clause(clause_id, -positive, -literal);
+            }
+        }
+        // Every model of the restricted formula assigns the variable and the literal the same
+        // value, so the weights of the variable are moved to the literal.
+        if let Some(weights) = self.weights.as_mut() {
+            let target = literal.unsigned_abs() - 1;
+            let (pos, neg) = weights[variable];
+            self.trail.push(TrailEntry::Weights(variable, weights[variable]));
+            self.trail.push(TrailEntry::Weights(target, weights[target]));
+            if literal > 0 {
+                weights[target].0 *= pos;
+                weights[target].1 *= neg;
+            } else {
+                weights[target].0 *= neg;
+                weights[target].1 *= pos;
+            }
+            weights[variable] = (1.0, 1.0);
+        }
+        self.repr[variable] = literal;
+        self.trail.push(TrailEntry::Substitute(variable));
+    }
+
+    /// Restricts the literal `v` to be equal to the literal `u` (both in DIMACS numbering)
+    fn make_literals_equal(&mut self, u: isize, v: isize) {
+        let u = self.literal_repr(u);
+        let v = self.literal_repr(v);
+        if u.unsigned_abs() == v.unsigned_abs() {
+            if u != v {
+                self.contradiction();
+            }
+            return;
+        }
+        match (self.literal_value(u), self.literal_value(v)) {
+            (Some(value_u), Some(value_v)) => {
+                if value_u != value_v {
+                    self.contradiction();
+                }
+                return;
+            },
+            (Some(value), None) => {
+                self.assign(if value { v } else { -v });
+                return;
+            },
+            (None, Some(value)) => {
+                self.assign(if value { u } else { -u });
+                return;
+            },
+            (None, None) => {},
+        };
+        // v = u is equivalent to var(v) = sign(v) * u
+        let literal = if v < 0 { -u } else { u };
+        self.substitute(v.unsigned_abs() - 1, literal);
+    }
+
+    /// Restricts the variable `v` to be equal to the variable `u`. The variable `v` is replaced by
+    /// `u` in every clause.
+    pub fn make_equal(&mut self, u: usize, v: usize) {
+        self.make_literals_equal(u as isize + 1, v as isize + 1);
+    }
+
+    /// Restricts the variable `v` to be different from the variable `u`. The variable `v` is
+    /// replaced by the negation of `u` in every clause.
+    pub fn make_not_equal(&mut self, u: usize, v: usize) {
+        self.make_literals_equal(-(u as isize + 1), v as isize + 1);
+    }
+
+    pub fn number_active_clauses(&self) -> usize {
+        self.active.iter().copied().filter(|&flag| flag).count()
+    }
+
+    pub fn log10_mult_factor(&self) -> f64 {
+        self.mult_factor.log10()
+    }
+
+    pub fn mult_factor(&self) -> &Count {
+        &self.mult_factor
+    }
+
+    pub fn iter_independent_set(&self) -> impl Iterator<Item = usize> {
+        self.independent_set.iter().copied()
+    }
+
+    /// Returns true if the satisfiability check proved that the formula is SAT
+    pub fn is_satisfiable(&self) -> bool {
+        self.witness.is_some()
+    }
+
+    /// Returns the log10 of the weight of the model found by the satisfiability check, projected
+    /// on the independent set, or None if no model has been found. It is a lower bound on the
+    /// count of the problem (0 for an unweighted problem, which has at least this model).
+    pub fn log10_witness_weight(&self) -> Option<f64> {
+        let witness = self.witness.as_ref()?;
+        if !self.is_weighted() {
+            return Some(0.0);
+        }
+        let mut seen = FxHashSet::<usize>::default();
+        let mut weight = 0.0;
+        for variable in self.independent_set.iter().copied() {
+            let repr = self.literal_repr(variable as isize + 1).unsigned_abs() - 1;
+            if !seen.insert(repr) {
+                continue;
+            }
+            let (pos, neg) = self.weight(repr);
+            weight += if witness[self.input_variable(repr)] { pos.log10() } else { neg.log10() };
+        }
+        Some(weight)
+    }
+
+    /// Returns the variable of the input formula corresponding to the variable of the problem
+    pub fn input_variable(&self, variable: usize) -> usize {
+        self.mapping.to_input(variable)
+    }
+
+    /// Returns the literal of the input formula corresponding to the literal of the problem
+    pub fn input_literal(&self, literal: isize) -> isize {
+        let variable = self.input_variable(literal.unsigned_abs() - 1) as isize + 1;
+        if literal < 0 { -variable } else { variable }
+    }
+
+    /// Returns what the variable of the input formula became in the problem, taking into account
+    /// the substitutions and fixed values of the current restriction level
+    pub fn input_variable_status(&self, variable: usize) -> InputVariable {
+        match self.mapping.to_problem(variable) {
+            InputVariable::Literal(literal) => {
+                let literal = self.literal_repr(literal);
+                match self.literal_value(literal) {
+                    Some(value) => InputVariable::Fixed(value),
+                    None => InputVariable::Literal(literal),
+                }
+            },
+            status => status,
+        }
+    }
+
+    /// Returns the number of variables of the input formula
+    pub fn number_input_var(&self) -> usize {
+        self.mapping.number_input_var()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged_variables_multiply_their_weights() {
+        let cnf = Cnf {
+            number_var: 3,
+            clauses: vec![vec![1, 2, 3], vec![-1, -3]],
+            weights: vec![(1, 0.5), (-1, 2.0), (2, 3.0), (-3, 0.25)],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, vec![0, 1, 2], Count::one(), false);
+        assert!(problem.is_weighted());
+        assert_eq!(problem.count_description(), "weighted model count");
+        assert_eq!(problem.weight(1), (3.0, 1.0));
+        problem.make_equal(0, 1);
+        assert_eq!(problem.weight(0), (1.5, 2.0));
+        assert_eq!(problem.weight(1), (1.0, 1.0));
+        problem.make_not_equal(0, 2);
+        assert_eq!(problem.weight(0), (0.375, 2.0));
+        assert_eq!(problem.weight(2), (1.0, 1.0));
+    }
+
+    type Snapshot = (Vec<Vec<isize>>, Vec<bool>, Vec<FxHashSet<usize>>, Vec<FxHashSet<usize>>, Vec<isize>);
+
+    fn snapshot(problem: &Problem) -> Snapshot {
+        (problem.clauses.clone(), problem.active.clone(), problem.var_pos_occ.clone(), problem.var_neg_occ.clone(), problem.repr.clone())
+    }
+
+    #[test]
+    fn backtracking_undoes_the_substitutions_of_the_level() {
+        let cnf = Cnf {
+            number_var: 4,
+            clauses: vec![vec![1, 2], vec![-1, 3, 4], vec![-2, -3], vec![2, 4]],
+            weights: vec![],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, vec![0, 1, 2, 3], Count::one(), false);
+        let initial = snapshot(&problem);
+
+        problem.new_level();
+        problem.make_equal(0, 1);
+        assert_eq!(problem.clause_at(0), &vec![1]);
+        assert_eq!(problem.clause_at(2), &vec![-1, -3]);
+        assert_eq!(problem.literal_repr(-2), -1);
+        let first = snapshot(&problem);
+
+        problem.new_level();
+        problem.make_not_equal(2, 3);
+        // -1 3 4 becomes the tautology -1 3 -3
+        assert!(!problem.is_clause_active(1));
+        assert_eq!(problem.clause_at(3), &vec![1, -3]);
+        assert!(problem.negative_occurences(2).contains(&3));
+        problem.make_equal(0, 2);
+        assert!(!problem.is_contradictory());
+        // 4 is already the negation of 3, and thus of 1
+        problem.make_equal(0, 3);
+        assert!(problem.is_contradictory());
+
+        problem.backtrack();
+        assert!(!problem.is_contradictory());
+        assert!(snapshot(&problem) == first);
+        problem.backtrack();
+        assert!(snapshot(&problem) == initial);
+        assert_eq!(problem.restriction_level(), 0);
+    }
+
+    #[test]
+    fn input_variables_follow_the_restrictions() {
+        let cnf = Cnf {
+            number_var: 4,
+            clauses: vec![vec![1, 2], vec![-2, 3]],
+            weights: vec![],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, vec![0, 1, 2], Count::one(), false);
+        problem.mapping.eliminate(3);
+        problem.new_level();
+        problem.make_not_equal(0, 1);
+        assert_eq!(problem.input_variable_status(1), InputVariable::Literal(-1));
+        problem.make_equal(0, 2);
+        problem.simplify();
+        // 1 2 becomes the tautology 1 -1 and -2 3 becomes the unit clause 1
+        assert_eq!(problem.input_variable_status(0), InputVariable::Fixed(true));
+        assert_eq!(problem.input_variable_status(1), InputVariable::Fixed(false));
+        assert_eq!(problem.input_variable_status(2), InputVariable::Fixed(true));
+        assert_eq!(problem.input_variable_status(3), InputVariable::Eliminated);
+        problem.backtrack();
+        assert_eq!(problem.input_variable_status(1), InputVariable::Literal(2));
+        assert_eq!(problem.input_literal(-3), -3);
+    }
+
+    #[test]
+    fn preprocessed_variables_are_numbered_densely() {
+        let input = || parse_dimacs("p cnf 7 4\n1 2 0\n-2 3 0\n4 5 0\n-5 -6 0\n".as_bytes()).unwrap();
+        // B+E eliminated 2 and 5, and 7 does not appear in the input
+        let bpe = || parse_dimacs("p cnf 6 2\n1 3 0\n4 -6 0\n".as_bytes()).unwrap();
+        let problem = Problem::from_preprocessed(&input(), bpe(), vec![0, 2, 3, 5], Count::one(), false);
+        assert_eq!(problem.number_var(), 5);
+        assert_eq!(problem.iter_clauses().cloned().collect::<Vec<Vec<isize>>>(), vec![vec![1, 2], vec![3, -4]]);
+        assert_eq!(problem.iter_independent_set().collect::<Vec<usize>>(), vec![0, 1, 2, 3]);
+        assert_eq!((0..5).map(|v| problem.input_variable(v)).collect::<Vec<usize>>(), vec![0, 2, 3, 5, 6]);
+        assert_eq!(problem.input_variable_status(1), InputVariable::Eliminated);
+        assert_eq!(problem.input_variable_status(5), InputVariable::Literal(4));
+        assert_eq!(problem.input_variable_status(6), InputVariable::Literal(5));
+        assert_eq!(problem.input_literal(-4), -6);
+        // The independent set loses 2, the models are counted on the remaining variables
+        let problem = Problem::from_preprocessed(&input(), bpe(), vec![0, 1], Count::one(), false);
+        assert_eq!(problem.iter_independent_set().collect::<Vec<usize>>(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn the_witness_weight_is_a_lower_bound() {
+        let cnf = Cnf {
+            number_var: 3,
+            clauses: vec![vec![1, 2], vec![-2, 3]],
+            weights: vec![(1, 0.5), (-2, 0.25), (3, 4.0)],
+            projection: Some(vec![1, 2]),
+        };
+        let mut problem = Problem::from_cnf(cnf, vec![0, 1], Count::one(), true);
+        // Without model (the satisfiability is unknown), there is no bound
+        assert!(!problem.is_satisfiable());
+        assert_eq!(problem.log10_witness_weight(), None);
+        problem.witness = Some(vec![true, false, true]);
+        assert!(problem.is_satisfiable());
+        // The weight of 3 is not counted since it is not projected
+        assert_eq!(problem.log10_witness_weight(), Some(0.5f64.log10() + 0.25f64.log10()));
+        problem.weights = None;
+        assert_eq!(problem.log10_witness_weight(), Some(0.0));
+    }
+}
@@ -0,0 +1,320 @@
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use cryptominisat::{Lbool, Lit, Solver as CMSSolver};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use super::Problem;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preprocessor {
+    /// The external B+E pre-processor, not aware of the projection set
+    Bpe,
+    /// Native pre-processing that preserves the projected count
+    Native,
+}
+
+/// Maximum number of occurences of a literal for a variable to be considered for elimination
+const BVE_MAX_OCCURENCES: usize = 16;
+
+fn cms_literal(literal: isize) -> Lit {
+    Lit::new((literal.unsigned_abs() - 1) as u32, literal < 0).unwrap()
+}
+
+fn remaining(deadline: Instant) -> Option<Duration> {
+    deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())
+}
+
+impl Problem {
+
+    /// Pre-processes the problem while preserving its projected count: detection of backbone
+    /// literals with CryptoMiniSat, substitution of equivalent literals and bounded variable
+    /// elimination of the non-projected variables. The pre-processing stops after `timeout`
+    /// seconds and must be done before any restriction level is opened.
+    pub fn preprocess(&mut self, timeout: u64) {
+        assert!(self.levels.is_empty(), "pre-processing with restrictions applied");
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+        log::trace!("Launching native pre-processing with {} seconds time limit", timeout);
+        let backbone = self.backbone(deadline);
+        log::trace!("Found {} backbone literals", backbone);
+        let equivalences = self.substitute_equivalences();
+        log::trace!("Substituted {} equivalent literals", equivalences);
+        let eliminated = self.eliminate_variables(deadline);
+        log::trace!("Eliminated {} non-projected variables", eliminated);
+        self.simplify();
+        if self.is_contradictory() {
+            log::info!("The pre-processing showed that the formula is UNSAT");
+            return;
+        }
+        log::info!("After native preprocess : {} active clauses", self.number_active_clauses());
+    }
+
+    /// Returns the variables replacing the projected variables. Other variables can be eliminated
+    /// without changing the projected count.
+    fn projected_roots(&self) -> FxHashSet<usize> {
+        self.independent_set.iter().map(|&v| self.literal_repr(v as isize + 1).unsigned_abs() - 1).collect()
+    }
+
+    /// Fixes the literals that are true in every model of the problem. Returns the number of
+    /// literals fixed.
+    fn backbone(&mut self, deadline: Instant) -> usize {
+        let mut solver = CMSSolver::new();
+        solver.new_vars(self.number_var);
+        for clause in self.iter_clauses() {
+            solver.add_clause(&clause.iter().map(|&l| cms_literal(l)).collect::<Vec<Lit>>());
+        }
+        for literal in self.iter_fixed_literals().collect::<Vec<isize>>() {
+            solver.add_clause(&[cms_literal(literal)]);
+        }
+        let Some(time) = remaining(deadline) else { return 0; };
+        solver.set_max_time(time.as_secs_f64());
+        if solver.solve() != Lbool::True {
+            return 0;
+        }
+        // Candidate backbone literals, as their value in the first model. A candidate is removed
+        // as soon as a model with the other value is found.
+        let model = solver.get_model();
+        let mut candidates = self.iter_clauses().flat_map(|clause| clause.iter().map(|l| l.unsigned_abs() - 1)).collect::<FxHashSet<usize>>().into_iter().map(|v| {
+            let literal = if model[v] == Lbool::False { -(v as isize + 1) } else { v as isize + 1 };
+            (v, literal)
+        }).collect::<Vec<(usize, isize)>>();
+        candidates.sort_unstable();
+        let mut expected = candidates.iter().copied().collect::<FxHashMap<usize, isize>>();
+        let mut backbone = vec![];
+        for (variable, literal) in candidates {
+            if !expected.contains_key(&variable) {
+                continue;
+            }
+            let Some(time) = remaining(deadline) else { break; };
+            solver.set_max_time(time.as_secs_f64());
+            match solver.solve_with_assumptions(&[!cms_literal(literal)]) {
+                Lbool::False => {
+                    solver.add_clause(&[cms_literal(literal)]);
+                    backbone.push(literal);
+                    expected.remove(&variable);
+                },
+                Lbool::True => {
+                    let model = solver.get_model();
+                    expected.retain(|&v, &mut l| (model[v] == Lbool::True) == (l > 0));
+                },
+                Lbool::Undef => break,
+            }
+        }
+        let number_backbone = backbone.len();
+        for literal in backbone {
+            if self.literal_value(literal).is_none() {
+                let literal = self.literal_repr(literal);
+                self.assign(literal);
+            }
+        }
+        self.simplify();
+        number_backbone
+    }
+
+    /// Substitutes the literals that are equivalent in the implication graph of the binary
+    /// clauses (i.e., in the same strongly connected component). Returns the number of
+    /// substituted literals.
+    fn substitute_equivalences(&mut self) -> usize {
+        // Literal l is the node 2 * (|l| - 1) + (l < 0)
+        let node = |literal: isize| 2 * (literal.unsigned_abs() - 1) + (literal < 0) as usize;
+        let literal_of = |node: usize| if node.is_multiple_of(2) { (node / 2) as isize + 1 } else { -((node / 2) as isize + 1) };
+        let mut graph = vec![vec![]; 2 * self.number_var];
+        for clause in self.iter_clauses().filter(|clause| clause.len() == 2) {
+            // a | b is -a => b and -b => a
+            graph[node(-clause[0])].push(node(clause[1]));
+            graph[node(-clause[1])].push(node(clause[0]));
+        }
+        let components = strongly_connected_components(&graph);
+        let projected = self.projected_roots();
+        let mut substituted = 0;
+        for component in components.into_iter().filter(|component| component.len() > 1) {
+            let literals = component.into_iter().map(literal_of).collect::<Vec<isize>>();
+            let representative = literals.iter().copied().find(|l| projected.contains(&(l.unsigned_abs() - 1))).unwrap_or(literals[0]);
+            for literal in literals {
+                // Each component has a complementary component, whose literals are already
+                // substituted when it is processed
+                if self.literal_repr(literal) != self.literal_repr(representative) {
+                    self.make_literals_equal(representative, literal);
+                    substituted += 1;
+                }
+            }
+        }
+        self.simplify();
+        substituted
+    }
+
+    /// Eliminates, by resolution, the non-projected variables whose elimination does not increase
+    /// the number of clauses. Returns the number of eliminated variables.
+    fn eliminate_variables(&mut self, deadline: Instant) -> usize {
+        let projected = self.projected_roots();
+        let mut candidates = (0..self.number_var).filter(|&v| {
+            !projected.contains(&v) && self.value[v].is_none() && self.literal_repr(v as isize + 1) == v as isize + 1
+        }).filter(|&v| {
+            let (pos, neg) = (self.var_pos_occ[v].len(), self.var_neg_occ[v].len());
+            pos + neg > 0 && pos <= BVE_MAX_OCCURENCES && neg <= BVE_MAX_OCCURENCES
+        }).collect::<Vec<usize>>();
+        candidates.sort_by_key(|&v| self.var_pos_occ[v].len() * self.var_neg_occ[v].len());
+        let mut eliminated = 0;
+        for variable in candidates {
+            if remaining(deadline).is_none() || self.is_contradictory() {
+                break;
+            }
+            // The simplifications after a previous elimination may have fixed or substituted it
+            if self.value[variable].is_some() || self.literal_repr(variable as isize + 1) != variable as isize + 1 {
+                continue;
+            }
+            let positive = self.var_pos_occ[variable].iter().copied().collect::<Vec<usize>>();
+            let negative = self.var_neg_occ[variable].iter().copied().collect::<Vec<usize>>();
+            if positive.len() > BVE_MAX_OCCURENCES || negative.len() > BVE_MAX_OCCURENCES {
+                continue;
+            }
+            let bound = positive.len() + negative.len();
+            let mut resolvents = vec![];
+            for &pos_clause in positive.iter() {
+                for &neg_clause in negative.iter() {
+                    if let Some(resolvent) = self.resolve(pos_clause, neg_clause, variable) {
+                        resolvents.push(resolvent);
+                    }
+                }
+                if resolvents.len() > bound {
+                    break;
+                }
+            }
+            if resolvents.len() > bound {
+                continue;
+            }
+            for clause_id in positive.into_iter().chain(negative) {
+                self.deactivate(clause_id);
+            }
+            for resolvent in resolvents {
+                if resolvent.is_empty() {
+                    self.contradiction();
+                } else {
+                    self.add_clause(resolvent);
+                }
+            }
+            self.mapping.eliminate(variable);
+            eliminated += 1;
+            self.simplify();
+        }
+        eliminated
+    }
+
+    /// Returns the resolvent of the two clauses on the variable, or None if it is a tautology
+    fn resolve(&self, pos_clause: usize, neg_clause: usize, variable: usize) -> Option<Vec<isize>> {
+        let mut resolvent = self.clauses[pos_clause].iter().copied().filter(|l| l.unsigned_abs() - 1 != variable).collect::<Vec<isize>>();
+        for literal in self.clauses[neg_clause].iter().copied().filter(|l| l.unsigned_abs() - 1 != variable) {
+            if resolvent.contains(&-literal) {
+                return None;
+            }
+            if !resolvent.contains(&literal) {
+                resolvent.push(literal);
+            }
+        }
+        Some(resolvent)
+    }
+}
+
+/// Iterative Tarjan's algorithm. Returns the strongly connected components of the graph.
+fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = graph.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low_link = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next_index = 0;
+    for root in 0..n {
+        if index[root] != usize::MAX {
+            continue;
+        }
+        // Call stack of (node, position of the next neighbor to visit)
+        let mut call_stack = vec![(root, 0)];
+        index[root] = next_index;
+        low_link[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        while let Some(top) = call_stack.last_mut() {
+            let node = top.0;
+            if top.1 < graph[node].len() {
+                let neighbor = graph[node][top.1];
+                top.1 += 1;
+                if index[neighbor] == usize::MAX {
+                    index[neighbor] = next_index;
+                    low_link[neighbor] = next_index;
+                    next_index += 1;
+                    stack.push(neighbor);
+                    on_stack[neighbor] = true;
+                    call_stack.push((neighbor, 0));
+                } else if on_stack[neighbor] {
+                    low_link[node] = low_link[node].min(index[neighbor]);
+                }
+            } else {
+                call_stack.pop();
+                if let Some(&(parent, _)) = call_stack.last() {
+                    low_link[parent] = low_link[parent].min(low_link[node]);
+                }
+                if low_link[node] == index[node] {
+                    let mut component = vec![];
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::Cnf;
+
+    /// Counts, by enumeration, the assignments of the projected variables that extend to a model
+    /// of the active clauses consistent with the substitutions and the fixed variables
+    fn projected_count(problem: &Problem) -> usize {
+        let mut projections = FxHashSet::<Vec<bool>>::default();
+        for assignment in 0..1usize << problem.number_var {
+            let value = |literal: isize| {
+                let literal = problem.literal_repr(literal);
+                let variable = literal.unsigned_abs() - 1;
+                let value = problem.value[variable].unwrap_or(assignment >> variable & 1 == 1);
+                value == (literal > 0)
+            };
+            let consistent = (0..problem.number_var).all(|v| value(v as isize + 1) == (assignment >> v & 1 == 1));
+            if consistent && problem.iter_clauses().all(|clause| clause.iter().any(|&l| value(l))) {
+                projections.insert(problem.independent_set.iter().map(|&v| value(v as isize + 1)).collect());
+            }
+        }
+        projections.len()
+    }
+
+    #[test]
+    fn equivalences_and_eliminations_preserve_the_projected_count() {
+        let cnf = Cnf {
+            number_var: 6,
+            clauses: vec![vec![1, -4], vec![-1, 4], vec![4, 5, 2], vec![-5, 3], vec![-5, -2, 6], vec![-6, 1, 3], vec![2, 3, 6]],
+            weights: vec![],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, vec![0, 1, 2], Count::one(), false);
+        let expected = projected_count(&problem);
+        assert_eq!(problem.substitute_equivalences(), 1);
+        // The projected variable is kept as representative
+        assert_eq!(problem.literal_repr(4), 1);
+        assert_eq!(projected_count(&problem), expected);
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert!(problem.eliminate_variables(deadline) > 0);
+        assert_eq!(projected_count(&problem), expected);
+        assert!(problem.iter_clauses().flatten().all(|l| l.unsigned_abs() <= 3));
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::time::Instant;
+
+use clap::ValueEnum;
+use cryptominisat::{Lbool, Lit, Solver as CMSSolver};
+
+use crate::parser::Cnf;
+use crate::supervisor::{Outcome, Supervised};
+use crate::tools;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SatSolver {
+    /// The bundled CryptoMiniSat, run in-process
+    Cryptominisat,
+    /// The external cadical solver
+    Cadical,
+}
+
+/// Result of the satisfiability check of the input
+pub enum Satisfiability {
+    /// The formula is SAT, with a model giving the value of each variable
+    Sat(Vec<bool>),
+    Unsat,
+    /// The solver did not decide within its time limit
+    Unknown,
+}
+
+impl SatSolver {
+
+    /// Checks the satisfiability of the formula, whose plain DIMACS is in the file `path`, within
+    /// `timeout` seconds
+    pub fn check(&self, cnf: &Cnf, path: &Path, timeout: u64) -> Satisfiability {
+        let start = Instant::now();
+        let result = match self {
+            Self::Cryptominisat => check_cryptominisat(cnf, timeout),
+            Self::Cadical => check_cadical(cnf, path, timeout),
+        };
+        log::trace!("Satisfiability checked in {:.2} seconds", start.elapsed().as_secs_f64());
+        match result {
+            Satisfiability::Sat(model) if !is_model(cnf, &model) => {
+                log::error!("The model found by the SAT solver does not satisfy the formula, it is discarded");
+                Satisfiability::Unknown
+            },
+            result => result,
+        }
+    }
+}
+
+/// Returns true if the assignment satisfies every clause of the formula
+fn is_model(cnf: &Cnf, model: &[bool]) -> bool {
+    model.len() == cnf.number_var && cnf.clauses.iter().all(|clause| {
+        clause.iter().any(|&l| model[l.unsigned_abs() - 1] == (l > 0))
+    })
+}
+
+fn check_cryptominisat(cnf: &Cnf, timeout: u64) -> Satisfiability {
+    let mut solver = CMSSolver::new();
+    solver.new_vars(cnf.number_var);
+    solver.set_max_time(timeout as f64);
+    for clause in cnf.clauses.iter() {
+        solver.add_clause(&clause.iter().map(|&l| Lit::new((l.unsigned_abs() - 1) as u32, l < 0).unwrap()).collect::<Vec<Lit>>());
+    }
+    match solver.solve() {
+        Lbool::True => Satisfiability::Sat(solver.get_model().iter().map(|&value| value == Lbool::True).collect()),
+        Lbool::False => Satisfiability::Unsat,
+        Lbool::Undef => Satisfiability::Unknown,
+    }
+}
+
+fn check_cadical(cnf: &Cnf, path: &Path, timeout: u64) -> Satisfiability {
+    let mut cadical = tools::command("cadical");
+    cadical.arg(path);
+    // Exit codes of cadical for SAT and UNSAT formulas. The model is given by the 'v' lines.
+    let outcome = Supervised::new("cadical", cadical).timeout(timeout).exit_codes(&[10, 20]).run(|code, output| {
+        if code == 20 {
+            return Some(Satisfiability::Unsat);
+        }
+        let mut model = vec![false; cnf.number_var];
+        for line in output.lines().filter(|line| line.starts_with("v ")) {
+            for literal in line.split_whitespace().skip(1) {
+                let literal = literal.parse::<isize>().ok()?;
+                if literal > 0 && literal.unsigned_abs() <= cnf.number_var {
+                    model[literal.unsigned_abs() - 1] = true;
+                }
+            }
+        }
+        Some(Satisfiability::Sat(model))
+    });
+    match outcome {
+        Outcome::Solved(result) => result,
+        Outcome::Timeout => Satisfiability::Unknown,
+        Outcome::MemOut | Outcome::ParseFailure | Outcome::Cancelled | Outcome::LaunchFailure(_) | Outcome::Unsupported => {
+            log::warn!("cadical did not decide the satisfiability of the formula");
+            Satisfiability::Unknown
+        },
+        Outcome::Crashed(status) => {
+            log::warn!("cadical crashed ({}) while checking the satisfiability of the formula", status);
+            Satisfiability::Unknown
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+
+    #[test]
+    fn models_are_checked_against_every_clause() {
+        let cnf = parse_dimacs("p cnf 3 2\n1 -2 0\n2 3 0\n".as_bytes()).unwrap();
+        assert!(is_model(&cnf, &[true, true, false]));
+        assert!(is_model(&cnf, &[false, false, true]));
+        assert!(!is_model(&cnf, &[false, true, true]));
+        assert!(!is_model(&cnf, &[true, true]));
+    }
+}
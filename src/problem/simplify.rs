@@ -0,0 +1,164 @@
+use rustc_hash::FxHashSet;
+
+use super::Problem;
+
+/// Number of simplifications done by a call to `Problem::simplify`
+#[derive(Default)]
+pub struct SimplifyStats {
+    pub units: usize,
+    pub equivalences: usize,
+    pub removed_clauses: usize,
+}
+
+impl Problem {
+
+    /// Simplifies the clauses modified since the last simplification, until a fixpoint is reached.
+    /// It propagates unit clauses, removes duplicated and subsumed clauses and substitutes the
+    /// equivalent literals found in binary clauses. All the modifications are done on the trail,
+    /// in the current restriction level.
+    ///
+    /// These simplifications preserve the (projected, weighted) count: fixed variables are
+    /// reported by `iter_fixed_literals` and removed from the projection, and equivalent
+    /// variables are replaced by their representative in `iter_restricted_projection`.
+    pub fn simplify(&mut self) -> SimplifyStats {
+        let mut stats = SimplifyStats::default();
+        let projected = self.independent_set.iter().copied().collect::<FxHashSet<usize>>();
+        while !self.is_contradictory() && !self.touched.is_empty() {
+            let touched = std::mem::take(&mut self.touched).into_iter().collect::<Vec<usize>>();
+
+            for clause_id in touched.iter().copied() {
+                if !self.active[clause_id] || self.clauses[clause_id].len() != 1 {
+                    continue;
+                }
+                let literal = self.clauses[clause_id][0];
+                self.assign(literal);
+                stats.units += 1;
+            }
+            if self.is_contradictory() {
+                break;
+            }
+
+            for clause_id in touched.iter().copied() {
+                if self.active[clause_id] {
+                    stats.removed_clauses += self.remove_subsumed(clause_id);
+                }
+            }
+
+            for clause_id in touched.iter().copied() {
+                if !self.active[clause_id] || self.clauses[clause_id].len() != 2 {
+                    continue;
+                }
+                let (x, y) = (self.clauses[clause_id][0], self.clauses[clause_id][1]);
+                // x | y and -x | -y means that x = -y
+                let complementary = self.var_occurences(-x).iter().copied().any(|other| self.clauses[other].len() == 2 && self.clauses[other].contains(&-y));
+                if complementary {
+                    // Keep a projected variable as representative when possible
+                    if projected.contains(&(y.unsigned_abs() - 1)) && !projected.contains(&(x.unsigned_abs() - 1)) {
+                        self.make_literals_equal(-y, x);
+                    } else {
+                        self.make_literals_equal(-x, y);
+                    }
+                    stats.equivalences += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    fn var_occurences(&self, literal: isize) -> &FxHashSet<usize> {
+        if literal < 0 {
+            &self.var_neg_occ[literal.unsigned_abs() - 1]
+        } else {
+            &self.var_pos_occ[literal.unsigned_abs() - 1]
+        }
+    }
+
+    /// Returns true if every literal of the first clause is in the second clause
+    fn is_subset(&self, clause_id: usize, other: usize) -> bool {
+        let other_clause = &self.clauses[other];
+        self.clauses[clause_id].iter().all(|literal| other_clause.contains(literal))
+    }
+
+    /// Deactivates the clauses subsumed by the clause, or the clause itself if it is subsumed by
+    /// another one. Returns the number of deactivated clauses.
+    fn remove_subsumed(&mut self, clause_id: usize) -> usize {
+        let size = self.clauses[clause_id].len();
+        if size == 0 {
+            return 0;
+        }
+        let mut removed = 0;
+        // A clause subsumed by this clause contains all its literals, in particular the one with
+        // the fewest occurences.
+        let literal = self.clauses[clause_id].iter().copied().min_by_key(|&l| self.var_occurences(l).len()).unwrap();
+        let candidates = self.var_occurences(literal).iter().copied().filter(|&other| other != clause_id && self.clauses[other].len() >= size).collect::<Vec<usize>>();
+        for other in candidates {
+            if self.is_subset(clause_id, other) {
+                self.deactivate(other);
+                removed += 1;
+            }
+        }
+        // The clause is subsumed by a clause that shares any of its literals
+        let subsumed = self.clauses[clause_id].iter().copied().any(|literal| {
+            self.var_occurences(literal).iter().copied().any(|other| other != clause_id && self.clauses[other].len() <= size && self.is_subset(other, clause_id))
+        });
+        if subsumed {
+            self.deactivate(clause_id);
+            removed += 1;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::Cnf;
+
+    #[test]
+    fn substitutions_are_propagated_until_fixpoint() {
+        let cnf = Cnf {
+            number_var: 8,
+            clauses: vec![vec![1, 2], vec![-1, 5, 6], vec![5, 6, 8], vec![5, 6], vec![-2, 4, 7], vec![-4, -7]],
+            weights: vec![],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, (0..8).collect(), Count::one(), false);
+        problem.new_level();
+        // 1 2 becomes the unit clause 1, which turns -1 5 6 into a duplicate of 5 6 and -2 4 7 into
+        // 4 7, the complement of -4 -7
+        problem.make_equal(0, 1);
+        let stats = problem.simplify();
+        assert_eq!((stats.units, stats.equivalences, stats.removed_clauses), (1, 1, 2));
+        assert!(!problem.is_contradictory());
+        assert_eq!(problem.number_active_clauses(), 1);
+        assert!(problem.is_clause_active(1) && problem.clause_at(1).len() == 2);
+        assert_eq!(problem.iter_fixed_literals().collect::<Vec<isize>>(), vec![1]);
+        assert_eq!(problem.literal_repr(7), -problem.literal_repr(4));
+        let mut projection = problem.iter_restricted_projection().collect::<Vec<usize>>();
+        projection.sort_unstable();
+        assert!(projection == vec![2, 3, 4, 5, 7] || projection == vec![2, 4, 5, 6, 7], "{:?}", projection);
+
+        problem.backtrack();
+        assert_eq!(problem.number_active_clauses(), 6);
+        assert_eq!(problem.iter_fixed_literals().count(), 0);
+        assert_eq!(problem.iter_restricted_projection().count(), 8);
+    }
+
+    #[test]
+    fn conflicting_units_make_the_problem_contradictory() {
+        let cnf = Cnf {
+            number_var: 3,
+            clauses: vec![vec![1, 2], vec![-1, 3], vec![-1, -3]],
+            weights: vec![],
+            projection: None,
+        };
+        let mut problem = Problem::from_cnf(cnf, (0..3).collect(), Count::one(), false);
+        problem.new_level();
+        problem.make_equal(0, 1);
+        problem.simplify();
+        assert!(problem.is_contradictory());
+        problem.backtrack();
+        assert!(!problem.is_contradictory());
+    }
+}
@@ -1,3 +1,5 @@
+use crate::problem::Problem;
+
 #[derive(Clone, Copy)]
 enum ConstraintType {
     Equality,
@@ -28,6 +30,30 @@ impl Constraint {
         }
     }
 
+    /// Applies the constraint on the problem, in its current restriction level, by substituting
+    /// its variables. Only the equalities and the constraints on two variables can be applied.
+    pub fn apply(&self, problem: &mut Problem) {
+        match (self.constraint_type, self.polarity, self.vars.len()) {
+            (ConstraintType::Equality, true, _) | (ConstraintType::Xor, false, 2) => {
+                for &variable in self.vars.iter().skip(1) {
+                    problem.make_equal(self.vars[0], variable);
+                }
+            },
+            (ConstraintType::Equality, false, 2) | (ConstraintType::Xor, true, 2) => {
+                problem.make_not_equal(self.vars[0], self.vars[1]);
+            },
+            _ => panic!("{} can not be applied on the problem", self),
+        }
+    }
+
+    /// Returns the same constraint on the variables of the input formula, used when reporting it
+    pub fn to_input(&self, problem: &Problem) -> Self {
+        Self {
+            vars: self.vars.iter().map(|&v| problem.input_variable(v)).collect(),
+            ..*self
+        }
+    }
+
 }
 
 impl std::fmt::Display for Constraint {
@@ -1,18 +1,13 @@
+#[allow(clippy::module_inception)]
 mod restricted;
 mod constraint;
-mod equals;
 mod xor;
 
 use clap::ValueEnum;
 
+pub use constraint::Constraint;
 pub use restricted::RestrictedSolver;
 
-#[derive(Clone, ValueEnum)]
-pub enum EqualityHeuristic {
-    MaxDegMostCommon,
-    MinContractionDeg,
-}
-
 #[derive(Clone, ValueEnum)]
 pub enum RestrictedMethod {
     Equality,
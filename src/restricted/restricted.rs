@@ -1,67 +1,225 @@
-use rand::prelude::*;
-use rand::seq::IteratorRandom;
+use std::sync::{Arc, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
+use crate::count::Count;
+use crate::counter::RestrictedFormula;
 use crate::problem::Problem;
 use crate::{Args, CTRL};
-use crate::tree_decomposition::compute_restrictions;
-use super::constraint::*;
+use crate::tree_decomposition::{compute_restrictions, write_tree_decomposition};
 
 pub struct RestrictedSolver {
-    problem: Problem,
+    pub(super) problem: Problem,
     exact: bool,
     bounds: Vec<(u64, f64)>,
+    /// Best lower bound (log10) found for each connected component of the problem
+    component_bounds: Vec<f64>,
+    /// Exact count of each connected component, once computed
+    component_counts: Vec<Option<Count>>,
+    /// Count of the projected variables that are not in any clause
+    free_factor: Count,
 }
 
 impl RestrictedSolver {
 
     pub fn new(args: &Args) -> Self {
         let problem = Problem::new(args);
-        if problem.is_empty() {
+        if Self::is_unsat(&problem) {
             log::info!("UNSAT");
             return Self {
                 problem,
                 exact: true,
                 bounds: vec![(0, f64::NEG_INFINITY)],
+                component_bounds: vec![],
+                component_counts: vec![],
+                free_factor: Count::one(),
             };
         }
-        // Before starting, the first bound is given by the log-10 factor found during the
-        // computation of the independent set as we know the formula is SAT, there is at least one
-        // model
-        let first_bound = problem.log10_mult_factor();
+        // Before starting, the first bound is given by the model found by the satisfiability check
+        // and the log-10 factor found during the computation of the independent set. For weighted
+        // formulas, the model counts for its weight, which can be below 1.
+        let first_bound = match problem.log10_witness_weight() {
+            Some(weight) => problem.log10_mult_factor() + weight,
+            None => f64::NEG_INFINITY,
+        };
         let elapsed = CTRL.elapsed();
         Self {
             problem,
             exact: false,
             bounds: vec![(elapsed, first_bound)],
+            component_bounds: vec![],
+            component_counts: vec![],
+            free_factor: Count::one(),
         }
     }
 
+    /// Records a new lower bound for the component and the resulting lower bound for the whole
+    /// problem
+    fn update_bound(&mut self, component: usize, bound: f64) {
+        if bound > self.component_bounds[component] {
+            self.component_bounds[component] = bound;
+            let global = self.problem.log10_mult_factor() + self.free_factor.log10() + self.component_bounds.iter().sum::<f64>();
+            log::info!("Lower bound on the log10 {} of the problem: {}", self.problem.count_description(), global);
+            self.bounds.push((CTRL.elapsed(), global));
+        }
+    }
+
+    /// Returns the lower bound (log10) on the count of each component before it is counted. If the
+    /// formula is proven SAT, each component has at least one model. Otherwise, or if the models
+    /// may weigh less than 1, there is no bound.
+    fn initial_component_bound(&self) -> f64 {
+        if self.problem.is_satisfiable() && !self.problem.is_weighted() { 0.0 } else { f64::NEG_INFINITY }
+    }
+
+    /// Returns true if the problem is known to be UNSAT, by the satisfiability check or by the
+    /// pre-processing
+    fn is_unsat(problem: &Problem) -> bool {
+        problem.is_empty() || problem.is_contradictory()
+    }
+
     pub fn solve(&mut self, args: &Args) {
-        let mut constraints: Vec<Constraint> = vec![];
+        // An UNSAT problem has no component to count
+        let mut components = if Self::is_unsat(&self.problem) { vec![] } else { self.problem.connected_components() };
+        // Small components are solved first, the time they do not use is given to the larger ones
+        components.sort_by_key(|component| component.number_clauses());
+        self.free_factor = self.problem.free_factor();
+        self.component_bounds = vec![self.initial_component_bound(); components.len()];
+        self.component_counts = vec![None; components.len()];
+        log::info!("The problem has {} connected components", components.len());
+        let mut exact = true;
+        let number_components = components.len();
+        for (index, mut component) in components.into_iter().enumerate() {
+            let timeout = CTRL.remaining(args.timeout) / (number_components - index) as u64;
+            let deadline = CTRL.elapsed() + timeout;
+            log::info!("Component {} with {} variables and {} clauses, {} seconds time limit", index, component.iter_independent_set().count(), component.number_clauses(), timeout);
+            exact &= self.solve_component(args, index, &mut component, deadline);
+        }
+        self.exact = exact;
+        if let Some(count) = self.count() {
+            log::info!("Exact {}: {}", self.problem.count_description(), count);
+        }
+    }
+
+    /// Returns the count of the problem, if the count of each component has been computed: the
+    /// product of the multiplication factor, the free variables factor and the component counts
+    pub fn count(&self) -> Option<Count> {
+        if !self.exact {
+            return None;
+        }
+        if Self::is_unsat(&self.problem) {
+            return Some(Count::zero());
+        }
+        let count = self.problem.mult_factor().multiply(&self.free_factor);
+        self.component_counts.iter().try_fold(count, |count, component| component.as_ref().map(|c| count.multiply(c)))
+    }
+
+    /// Computes lower bounds on the count of the component, until its exact count is found or the
+    /// deadline (in seconds since start) is reached. Returns true if the exact count is found.
+    fn solve_component(&mut self, args: &Args, index: usize, component: &mut Problem, deadline: u64) -> bool {
+        if let Some(directory) = args.td_output.as_ref() {
+            let path = directory.join(format!("component-{}.td", index));
+            if let Err(error) = std::fs::create_dir_all(directory).and_then(|_| write_tree_decomposition(args, component, &path)) {
+                log::warn!("Can not write the tree decomposition in {}: {}", path.display(), error);
+            }
+        }
+        let constraints = compute_restrictions(args, component);
         log::info!("Number of constraints to partition the space: {}", constraints.len());
+        for constraint in constraints.iter() {
+            log::trace!("Restriction {}", constraint.to_input(component));
+        }
+        // Number of restrictions kept at each level, from the most restricted formula to the
+        // formula without restrictions whose count is exact
+        let mut levels = vec![];
         if !constraints.is_empty() {
-            log::info!("Starting lower-bound computations with {} seconds remaining", CTRL.remaining(args.timeout));
+            log::info!("Starting lower-bound computations with {} seconds remaining", CTRL.remaining(deadline));
             let nb_restrictions = constraints.len() as f64;
             let n_calls = nb_restrictions.log2().ceil() as usize;
             let chunk_size = (nb_restrictions / n_calls as f64).ceil() as usize;
             log::info!("Chunk size for restriction removal is {}", chunk_size);
-            while !constraints.is_empty() && CTRL.remaining(args.timeout) > 0 {
-                if let Some(lb) = args.counter().lower_bound(&self.problem, &constraints, CTRL.remaining(args.timeout)) {
-                    log::info!("Lower bound on the log-model-count {} ({} restrictions)", lb, constraints.len());
-                    self.bounds.push((CTRL.elapsed(), lb));
-                } 
-                let new_length = if constraints.len() > chunk_size { constraints.len() - chunk_size } else { 0 };
-                constraints.truncate(new_length);
+            let mut length = constraints.len();
+            while length > 0 {
+                levels.push(length);
+                length = length.saturating_sub(chunk_size);
+            }
+        }
+        levels.push(0);
+        // Each level is applied and simplified on the component in its own restriction level, from
+        // the least restricted one, so that relaxing the formula only requires to backtrack
+        let start_level = component.restriction_level();
+        let mut applied = 0;
+        for &length in levels.iter().rev().skip(1) {
+            component.new_level();
+            for constraint in constraints[applied..length].iter() {
+                constraint.apply(component);
             }
+            let stats = component.simplify();
+            log::trace!("Simplification of the level with {} restrictions: {} units, {} equivalences, {} clauses removed", length, stats.units, stats.equivalences, stats.removed_clauses);
+            applied = length;
         }
-        if CTRL.remaining(args.timeout) > 0 {
-            log::trace!("Computing the true model count");
-            if let Some(model_count) = args.counter().lower_bound(&self.problem, &constraints, CTRL.remaining(args.timeout)) {
-                log::info!("Exact log-model-count is {}", model_count);
-                self.bounds.push((CTRL.elapsed(), model_count));
-                self.exact = true;
+        let jobs = args.jobs.max(1);
+        let mut exact = false;
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            // Levels being counted, with the flag cancelling their counter
+            let mut running: Vec<(usize, Arc<AtomicBool>)> = vec![];
+            let mut next_level = 0;
+            loop {
+                while running.len() < jobs && next_level < levels.len() && CTRL.remaining(deadline) > 0 {
+                    let level = next_level;
+                    while component.restriction_level() > start_level + levels.len() - 1 - level {
+                        component.backtrack();
+                    }
+                    let formula = RestrictedFormula::new(component);
+                    let cancel = Arc::new(AtomicBool::new(false));
+                    running.push((level, cancel.clone()));
+                    let sender = sender.clone();
+                    let timeout = CTRL.remaining(deadline);
+                    if levels[level] == 0 {
+                        log::trace!("Computing the true model count");
+                    }
+                    scope.spawn(move || {
+                        let count = args.counter().lower_bound(&formula, timeout, Some(cancel));
+                        let _ = sender.send((level, count));
+                    });
+                    next_level += 1;
+                }
+                if running.is_empty() {
+                    break;
+                }
+                let (level, count) = receiver.recv().unwrap();
+                running.retain(|&(running_level, _)| running_level != level);
+                let Some(count) = count else { continue; };
+                if levels[level] == 0 {
+                    log::info!("Exact {} of the component is {}", component.count_description(), count);
+                    self.component_counts[index] = Some(count.clone());
+                    exact = true;
+                } else {
+                    log::info!("Lower bound on the log10 {} {} ({} restrictions)", component.count_description(), count.log10(), levels[level]);
+                }
+                self.update_bound(index, count.log10());
+                // The more restricted levels can not give a better bound
+                for (_, cancel) in running.iter().filter(|&&(running_level, _)| running_level < level) {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+                if exact {
+                    break;
+                }
             }
+        });
+        while component.restriction_level() > start_level {
+            component.backtrack();
         }
+        exact
+    }
+
+    /// Returns true if the solver computes a weighted model count
+    pub fn is_weighted(&self) -> bool {
+        self.problem.is_weighted()
+    }
+
+    /// Returns true if the input declares a projection set
+    pub fn is_projected(&self) -> bool {
+        self.problem.is_projected()
     }
 
     #[allow(dead_code)]
@@ -69,3 +227,26 @@ impl RestrictedSolver {
         self.bounds.iter().copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_dimacs;
+
+    #[test]
+    fn components_have_no_bound_when_the_satisfiability_is_unknown() {
+        // Built without satisfiability check, as when the check reaches its time limit
+        let cnf = parse_dimacs("p cnf 4 3\n1 2 0\n-1 -2 0\n3 4 0\n".as_bytes()).unwrap();
+        let problem = Problem::from_cnf(cnf, (0..4).collect(), Count::one(), false);
+        assert!(!problem.is_satisfiable());
+        let solver = RestrictedSolver {
+            problem,
+            exact: false,
+            bounds: vec![(0, f64::NEG_INFINITY)],
+            component_bounds: vec![],
+            component_counts: vec![],
+            free_factor: Count::one(),
+        };
+        assert_eq!(solver.initial_component_bound(), f64::NEG_INFINITY);
+    }
+}
@@ -0,0 +1,302 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval at which the running tools are checked against their limits
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Memory limit, in bytes, of every external tool
+static MEMORY_LIMIT: OnceLock<u64> = OnceLock::new();
+
+/// Sets the memory limit, in megabytes, of every external tool launched afterwards
+pub fn set_memory_limit(megabytes: u64) {
+    let _ = MEMORY_LIMIT.set(megabytes * 1024 * 1024);
+}
+
+/// Result of a supervised run of an external tool
+#[derive(Debug)]
+pub enum Outcome<T> {
+    /// The tool finished and its output has been parsed
+    Solved(T),
+    /// The tool has been killed after reaching its time limit
+    Timeout,
+    /// The tool has been killed after reaching the memory limit
+    MemOut,
+    /// The tool has been killed by a signal or exited with an unexpected code
+    Crashed(ExitStatus),
+    /// The tool finished but its output could not be parsed
+    ParseFailure,
+    /// The tool has been killed because its result is no longer needed
+    Cancelled,
+    /// The tool could not be launched or waited for
+    LaunchFailure(io::Error),
+    /// The tool does not handle the input, e.g. a native counter given too many variables
+    Unsupported,
+}
+
+/// An external tool launched with a wall-clock limit and the global memory limit. Its standard
+/// output is kept to be parsed and its standard error is written to the log.
+///
+/// The tool runs in its own process group, so that the processes it launches are killed with it.
+/// The memory limit applies to the resident memory of the whole group, checked at each poll. The
+/// address space is not limited: a tool failing its allocations would look like a crash, and some
+/// tools reserve much more virtual memory than they use.
+pub struct Supervised {
+    name: String,
+    command: Command,
+    timeout: Option<Duration>,
+    memory_limit: Option<u64>,
+    exit_codes: Vec<i32>,
+    cancel: Option<Arc<AtomicBool>>,
+    grace: Option<Duration>,
+}
+
+/// Sends the signal to every process of the process group
+fn kill_group(group: u32, signal: libc::c_int) {
+    // SAFETY: the group is the one of a child that has not been waited for, so that its id can not
+    // have been reused by another group
+    unsafe { libc::kill(-(group as libc::pid_t), signal); }
+}
+
+/// Returns true if the child has terminated, without waiting for it, so that its process group
+/// can still be killed
+fn has_exited(pid: u32) -> io::Result<bool> {
+    // SAFETY: an all-zero siginfo_t is valid, and waitid only writes into it
+    let mut info = unsafe { std::mem::zeroed::<libc::siginfo_t>() };
+    // SAFETY: the pointer is valid for the duration of the call
+    if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOHANG | libc::WNOWAIT) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: waitid filled the fields of the child, or left the pid to 0 if it is still running
+    Ok(unsafe { info.si_pid() } != 0)
+}
+
+/// Returns the resident memory, in bytes, of the process
+fn resident_memory(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+/// Returns the resident memory, in bytes, of all the processes of the process group
+fn group_resident_memory(group: u32) -> u64 {
+    let Ok(entries) = std::fs::read_dir("/proc") else { return 0; };
+    entries.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok()).filter(|&pid| {
+        // The process group is the third field after the command name, which may contain spaces
+        // and parentheses
+        std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok().and_then(|stat| {
+            stat.rsplit_once(')')?.1.split_whitespace().nth(2)?.parse::<u32>().ok()
+        }) == Some(group)
+    }).filter_map(resident_memory).sum()
+}
+
+impl Supervised {
+
+    pub fn new(name: &str, command: Command) -> Self {
+        Self {
+            name: name.to_string(),
+            command,
+            timeout: None,
+            memory_limit: MEMORY_LIMIT.get().copied(),
+            exit_codes: vec![0],
+            cancel: None,
+            grace: None,
+        }
+    }
+
+    /// Kills the tool after the given number of seconds
+    pub fn timeout(mut self, seconds: u64) -> Self {
+        self.timeout = Some(Duration::from_secs(seconds));
+        self
+    }
+
+    /// Kills the tool as soon as the flag is set
+    pub fn cancel_flag(mut self, cancel: Option<Arc<AtomicBool>>) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// At the time limit, asks the tool to stop with SIGTERM instead of killing it, and parses the
+    /// output it prints within the given number of seconds. Used for anytime tools that print
+    /// their best result when they are interrupted.
+    pub fn grace_period(mut self, seconds: u64) -> Self {
+        self.grace = Some(Duration::from_secs(seconds));
+        self
+    }
+
+    /// Exit codes of a normal termination of the tool (only 0 by default)
+    pub fn exit_codes(mut self, codes: &[i32]) -> Self {
+        self.exit_codes = codes.to_vec();
+        self
+    }
+
+    /// Runs the tool until it terminates or reaches one of its limits. On normal termination,
+    /// `parse` is given the exit code and the standard output of the tool.
+    pub fn run<T>(mut self, parse: impl FnOnce(i32, &str) -> Option<T>) -> Outcome<T> {
+        self.command.stdout(Stdio::piped()).stderr(Stdio::piped()).process_group(0);
+        let mut child = match self.command.spawn() {
+            Ok(child) => child,
+            Err(error) => {
+                log::error!("Can not launch {}: {}", self.name, error);
+                return Outcome::LaunchFailure(error);
+            },
+        };
+        let mut stdout = child.stdout.take().unwrap();
+        let stdout_reader = thread::spawn(move || {
+            let mut output = vec![];
+            let _ = stdout.read_to_end(&mut output);
+            String::from_utf8_lossy(&output).into_owned()
+        });
+        let stderr = child.stderr.take().unwrap();
+        let name = self.name.clone();
+        let stderr_reader = thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                log::debug!("[{}] {}", name, line);
+            }
+        });
+        // A time limit too far away to be represented is no limit
+        let mut deadline = self.timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut killed = None;
+        let mut interrupted = false;
+        let status = loop {
+            match has_exited(child.id()) {
+                Ok(true) => {
+                    // The processes launched by the tool may still hold its output open
+                    kill_group(child.id(), libc::SIGKILL);
+                    break child.wait();
+                },
+                Ok(false) => {},
+                Err(error) => {
+                    log::error!("Can not wait for {}: {}", self.name, error);
+                    kill_group(child.id(), libc::SIGKILL);
+                    break Err(error);
+                },
+            }
+            if self.cancel.as_ref().is_some_and(|cancel| cancel.load(Ordering::Relaxed)) {
+                killed = Some(Outcome::Cancelled);
+            } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                match self.grace {
+                    Some(grace) if !interrupted => {
+                        kill_group(child.id(), libc::SIGTERM);
+                        interrupted = true;
+                        deadline = Instant::now().checked_add(grace);
+                    },
+                    _ => killed = Some(Outcome::Timeout),
+                }
+            } else if self.memory_limit.is_some_and(|limit| group_resident_memory(child.id()) > limit) {
+                killed = Some(Outcome::MemOut);
+            }
+            if killed.is_some() {
+                kill_group(child.id(), libc::SIGKILL);
+                break child.wait();
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        let output = stdout_reader.join().unwrap_or_default();
+        let _ = stderr_reader.join();
+        let status = match status {
+            Ok(status) => status,
+            Err(error) => return Outcome::LaunchFailure(error),
+        };
+        if let Some(outcome) = killed {
+            match outcome {
+                Outcome::Timeout => log::trace!("{} killed after reaching its time limit", self.name),
+                Outcome::MemOut => log::trace!("{} killed after reaching the memory limit", self.name),
+                _ => log::trace!("{} cancelled", self.name),
+            }
+            return outcome;
+        }
+        if interrupted {
+            log::trace!("{} interrupted after reaching its time limit", self.name);
+            return match parse(status.code().unwrap_or(-1), &output) {
+                Some(result) => Outcome::Solved(result),
+                None => Outcome::Timeout,
+            };
+        }
+        match status.code() {
+            Some(code) if self.exit_codes.contains(&code) => match parse(code, &output) {
+                Some(result) => Outcome::Solved(result),
+                None => Outcome::ParseFailure,
+            },
+            _ => Outcome::Crashed(status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(script: &str) -> Supervised {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        Supervised::new("sh", command)
+    }
+
+    #[test]
+    fn outcomes_follow_the_termination_of_the_tool() {
+        let parse = |code: i32, output: &str| output.trim().parse::<i32>().ok().map(|value| (code, value));
+        assert!(matches!(shell("echo 12").run(parse), Outcome::Solved((0, 12))));
+        assert!(matches!(shell("echo 3; exit 10").exit_codes(&[10, 20]).run(parse), Outcome::Solved((10, 3))));
+        assert!(matches!(shell("echo 3; exit 10").run(parse), Outcome::Crashed(status) if status.code() == Some(10)));
+        assert!(matches!(shell("kill -9 $$").run(parse), Outcome::Crashed(status) if status.code().is_none()));
+        assert!(matches!(shell("echo twelve").run(parse), Outcome::ParseFailure));
+        let start = Instant::now();
+        assert!(matches!(shell("exec sleep 30").timeout(1).run(parse), Outcome::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn missing_tool_is_a_launch_failure() {
+        let outcome = Supervised::new("missing", Command::new("/nonexistent/tool")).run(|_, _| Some(()));
+        assert!(matches!(outcome, Outcome::LaunchFailure(_)));
+    }
+
+    #[test]
+    fn timeout_kills_the_processes_launched_by_the_tool() {
+        // The background sleep keeps the output open: the run only ends if it is killed too
+        let start = Instant::now();
+        assert!(matches!(shell("sleep 30 & sleep 30").timeout(1).run(|_, _| Some(())), Outcome::Timeout));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn memory_limit_applies_to_the_processes_launched_by_the_tool() {
+        // The inner shell holds a string of 100 MB, above the limit of 64 MB
+        let mut supervised = shell(r#"sh -c 'x=$(head -c 100000000 /dev/zero | tr "\0" a); sleep 30'; sleep 30"#).timeout(20);
+        supervised.memory_limit = Some(64 * 1024 * 1024);
+        let start = Instant::now();
+        assert!(matches!(supervised.run(|_, _| Some(())), Outcome::MemOut));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let mut supervised = shell("echo 1");
+        supervised.memory_limit = Some(64 * 1024 * 1024);
+        assert!(matches!(supervised.run(|_, _| Some(())), Outcome::Solved(())));
+    }
+
+    #[test]
+    fn setting_the_flag_cancels_the_tool() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let flag = cancel.clone();
+        let start = Instant::now();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            flag.store(true, Ordering::Relaxed);
+        });
+        let outcome = shell("exec sleep 30").timeout(20).cancel_flag(Some(cancel)).run(|_, _| Some(()));
+        canceller.join().unwrap();
+        assert!(matches!(outcome, Outcome::Cancelled));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn processes_left_by_the_tool_are_killed() {
+        let start = Instant::now();
+        let outcome = shell("sleep 30 & echo done").run(|_, output| Some(output.trim().to_string()));
+        assert!(matches!(outcome, Outcome::Solved(output) if output == "done"));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio, exit};
+use std::sync::{LazyLock, Mutex, OnceLock};
+
+use regex::Regex;
+use rustc_hash::FxHashMap;
+
+use crate::Args;
+use crate::count::Count;
+use crate::counter::{Counter, ModelCounter, RestrictedFormula};
+use crate::parser::{Cnf, parse_dimacs};
+use crate::problem::{Preprocessor, Problem, SatSolver};
+use crate::supervisor::{Outcome, Supervised};
+
+/// Time limit, in seconds, of the probes of the tools
+const PROBE_TIMEOUT: u64 = 10;
+
+/// Formula used to probe the tools: x1 | x2 and -x1, with a single model
+const PROBE_CNF: &str = "p cnf 2 2\n1 2 0\n-1 0\n";
+
+/// Formula used to probe the counters: x1 | x2, with 3 models. It has no unit clause, so that the
+/// simplification of the restricted formulas leaves it to the counter.
+const PROBE_COUNTER_CNF: &str = "p cnf 2 1\n1 2 0\n";
+
+/// Model count of the counter probe formula
+const PROBE_COUNTER_MODELS: f64 = 3.0;
+
+/// Paths of the tools given in the configuration file
+static CONFIG: OnceLock<FxHashMap<String, PathBuf>> = OnceLock::new();
+
+/// Version of the tools that have been probed, `None` for the missing ones
+static PROBED: Mutex<Option<FxHashMap<String, Option<String>>>> = Mutex::new(None);
+
+/// Reads the tool paths from a configuration file made of `name = path` lines (lines starting
+/// with '#' are comments)
+pub fn load_config(path: &Path) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) => {
+            log::error!("Can not read the tools configuration {}: {}", path.display(), error);
+            exit(1);
+        },
+    };
+    let mut config = FxHashMap::default();
+    for (index, line) in BufReader::new(file).lines().map_while(Result::ok).enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, tool_path)) => {
+                config.insert(name.trim().to_string(), PathBuf::from(tool_path.trim()));
+            },
+            None => {
+                log::error!("Invalid line {} in the tools configuration {}: '{}'", index + 1, path.display(), line);
+                exit(1);
+            },
+        }
+    }
+    let _ = CONFIG.set(config);
+}
+
+/// Returns the environment variable giving the path of the tool, e.g. `COUAINCRE_D4` for d4
+fn environment_variable(name: &str) -> String {
+    let name = name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect::<String>();
+    format!("COUAINCRE_{}", name)
+}
+
+/// Returns the path of the tool: from its environment variable, then from the configuration file,
+/// and by default its name, looked up in the PATH
+pub fn path(name: &str) -> PathBuf {
+    if let Some(path) = std::env::var_os(environment_variable(name)).filter(|path| !path.is_empty()) {
+        return PathBuf::from(path);
+    }
+    if let Some(path) = CONFIG.get().and_then(|config| config.get(name)) {
+        return path.clone();
+    }
+    PathBuf::from(name)
+}
+
+/// Returns a command launching the tool
+pub fn command(name: &str) -> Command {
+    Command::new(path(name))
+}
+
+/// Returns true if the executable of the tool exists
+fn exists(name: &str) -> bool {
+    let path = path(name);
+    if path.components().count() > 1 {
+        return path.is_file();
+    }
+    std::env::var_os("PATH").is_some_and(|dirs| std::env::split_paths(&dirs).any(|dir| dir.join(&path).is_file()))
+}
+
+/// Returns the first line of the output that contains a version number (e.g. 2.5.4 or v1.0) or a
+/// commit hash
+fn parse_version(output: &str) -> Option<String> {
+    static VERSION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(^|[\s(])(v?\d+(\.\d+)+|[0-9a-f]{7,40})([\s),]|$)").unwrap());
+    output.lines().map(str::trim).find(|line| VERSION.is_match(line)).map(String::from)
+}
+
+/// Returns the version printed by `<tool> --version`, or "unknown" if the tool fails or does not
+/// print a version. The tool does not get a standard input, so that a tool reading its formula
+/// from it does not wait.
+fn detect_version(name: &str) -> String {
+    let mut version = command(name);
+    version.arg("--version").stdin(Stdio::null());
+    let outcome = Supervised::new(name, version).timeout(PROBE_TIMEOUT).run(|_, output| parse_version(output));
+    match outcome {
+        Outcome::Solved(version) => version,
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Probes the tool with `probe`, which returns true if the tool answered correctly, and records
+/// its version. Returns true if the tool can be used.
+fn probe(name: &str, probe: impl FnOnce() -> bool) -> bool {
+    let available = exists(name) && probe();
+    let version = available.then(|| detect_version(name));
+    match version.as_ref() {
+        Some(version) => log::info!("Using {} ({}), version {}", name, path(name).display(), version),
+        None => log::warn!("{} ({}) is missing or does not work", name, path(name).display()),
+    }
+    PROBED.lock().unwrap().get_or_insert_default().insert(name.to_string(), version);
+    available
+}
+
+/// Returns true if the tool has been probed successfully
+pub fn is_available(name: &str) -> bool {
+    PROBED.lock().unwrap().as_ref().and_then(|probed| probed.get(name).cloned()).flatten().is_some()
+}
+
+/// Returns the version of the tool, if it has been probed successfully
+pub fn version(name: &str) -> Option<String> {
+    PROBED.lock().unwrap().as_ref().and_then(|probed| probed.get(name).cloned()).flatten()
+}
+
+/// Identifies the installed tool by its resolved path and its version, so that the results
+/// cached for a tool are not reused once it is replaced or upgraded
+pub fn identity(name: &str) -> String {
+    format!("{} {}", path(name).display(), version(name).unwrap_or_default())
+}
+
+/// Runs the tool on the probe formula, given as argument, and returns true if `accept` accepts
+/// its exit code and output
+fn run_on_probe(name: &str, input: &Path, exit_codes: &[i32], accept: impl FnOnce(i32, &str) -> bool) -> bool {
+    let mut tool = command(name);
+    tool.arg(input);
+    let outcome = Supervised::new(name, tool).timeout(PROBE_TIMEOUT).exit_codes(exit_codes).run(|code, output| accept(code, output).then_some(()));
+    matches!(outcome, Outcome::Solved(()))
+}
+
+/// Returns true if the counter gives the count of the counter probe formula. The native counters
+/// are not probed.
+fn probe_counter(counter: &dyn ModelCounter) -> bool {
+    if counter.is_native() {
+        log::info!("Using the native {} counter", counter.name());
+        return true;
+    }
+    let cnf = parse_dimacs(PROBE_COUNTER_CNF.as_bytes()).unwrap();
+    let formula = RestrictedFormula::new(&Problem::from_cnf(cnf, vec![0, 1], Count::one(), false));
+    probe(counter.executable(), || {
+        counter.lower_bound(&formula, PROBE_TIMEOUT, None).is_some_and(|count| (count.log10() - PROBE_COUNTER_MODELS.log10()).abs() < 1e-6)
+    })
+}
+
+/// Probes the tools used with the arguments and falls back to other tools for the missing ones:
+/// CryptoMiniSat for cadical, native pre-processing for B+E, ganak for d4 and the native DPLL
+/// counter for the other counters. A missing arjun or tree decomposition solver is skipped.
+pub fn discover(args: &mut Args) {
+    if let Some(config) = args.tools_config.as_ref() {
+        load_config(config);
+    }
+    let input = std::env::temp_dir().join(format!("couaincre-{}-probe.cnf", std::process::id()));
+    if let Err(error) = std::fs::write(&input, PROBE_CNF) {
+        log::error!("Can not write the probe formula {}: {}", input.display(), error);
+        exit(1);
+    }
+    if args.sat_solver == SatSolver::Cadical && !probe("cadical", || run_on_probe("cadical", &input, &[10, 20], |code, _| code == 10)) {
+        log::warn!("Falling back to CryptoMiniSat for the satisfiability check");
+        args.sat_solver = SatSolver::Cryptominisat;
+    }
+    // B+E may only declare the variables up to the largest one it does not eliminate
+    let bpe_works = |_: i32, output: &str| parse_dimacs(output.as_bytes()).is_ok_and(|cnf: Cnf| cnf.number_var <= 2);
+    if args.preprocessor == Preprocessor::Bpe && !probe("bpe", || run_on_probe("bpe", &input, &[0], bpe_works)) {
+        log::warn!("Falling back to native pre-processing");
+        args.preprocessor = Preprocessor::Native;
+    }
+    if !probe("arjun", || run_on_probe("arjun", &input, &[0], |_, output| output.lines().any(|line| line.starts_with("c p show")))) {
+        log::warn!("The independent set will not be minimized with arjun");
+    }
+    let counter = args.counter.build(args.counter_config.as_deref());
+    if !probe_counter(counter.as_ref()) {
+        let fallback = matches!(args.counter, Counter::D4).then(|| Counter::Ganak.build(None)).filter(|ganak| probe_counter(ganak.as_ref()));
+        if fallback.is_some() {
+            log::warn!("{} is not available, falling back to ganak for model counting", counter.name());
+            args.counter = Counter::Ganak;
+        } else {
+            log::warn!("No model counter available among {}{}, falling back to the native DPLL counter, which only suits moderate formulas", counter.name(), if matches!(args.counter, Counter::D4) { " and ganak" } else { "" });
+            args.counter = Counter::Dpll;
+        }
+    }
+    if let Some(solver) = args.td_solver.as_deref() && !exists(solver) {
+        log::warn!("{} ({}) is missing, the treewidth will be estimated with the min-fill heuristic", solver, path(solver).display());
+        args.td_solver = None;
+    }
+    let _ = std::fs::remove_file(&input);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_variables_are_derived_from_the_names() {
+        assert_eq!(environment_variable("d4"), "COUAINCRE_D4");
+        assert_eq!(environment_variable("sharpSAT-TD"), "COUAINCRE_SHARPSAT_TD");
+    }
+
+    #[test]
+    fn configured_tools_are_probed() {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-tools", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let script = directory.join("versioned.sh");
+        std::fs::write(&script, "#!/bin/sh\necho\necho 'versioned 1.2.3'\n").unwrap();
+        let mut permissions = std::fs::metadata(&script).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        std::fs::set_permissions(&script, permissions).unwrap();
+        let config = directory.join("tools.conf");
+        std::fs::write(&config, format!("# test tools\nversioned-test-tool = {}\nmissing-test-tool = {}\n", script.display(), directory.join("missing").display())).unwrap();
+        load_config(&config);
+        assert_eq!(path("versioned-test-tool"), script);
+        assert_eq!(path("unconfigured-test-tool"), PathBuf::from("unconfigured-test-tool"));
+        assert!(probe("versioned-test-tool", || true));
+        assert_eq!(version("versioned-test-tool"), Some("versioned 1.2.3".to_string()));
+        assert!(!probe("missing-test-tool", || true));
+        assert!(!is_available("missing-test-tool"));
+        assert!(!probe("versioned-test-tool", || false));
+        assert_eq!(version("versioned-test-tool"), None);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn versions_are_only_read_from_successful_runs() {
+        assert_eq!(parse_version("ganak\nc version: 2.5.4\n"), Some("c version: 2.5.4".to_string()));
+        assert_eq!(parse_version("arjun v1.0 (3f2a9c1e)"), Some("arjun v1.0 (3f2a9c1e)".to_string()));
+        assert_eq!(parse_version("d4 e0a5ac3bbe\n"), Some("d4 e0a5ac3bbe".to_string()));
+        assert_eq!(parse_version("Error: unknown option --version\nUsage: tool [options] <input>\n"), None);
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-versions", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let script = |name: &str, content: &str| {
+            let script = directory.join(name);
+            std::fs::write(&script, format!("#!/bin/sh\n{}\n", content)).unwrap();
+            let mut permissions = std::fs::metadata(&script).unwrap().permissions();
+            std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+            std::fs::set_permissions(&script, permissions).unwrap();
+            script.to_string_lossy().into_owned()
+        };
+        // A tool reading its input from the standard input gets an empty one
+        assert_eq!(detect_version(&script("stdin.sh", "cat > /dev/null\necho 'stdin 1.2'")), "stdin 1.2");
+        assert_eq!(detect_version(&script("failing.sh", "echo 'option 1.2 is unknown'\nexit 1")), "unknown");
+        assert_eq!(detect_version(&script("usage.sh", "echo 'usage: tool <input>'")), "unknown");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,12 +1,44 @@
 use clap::ValueEnum;
-use rustc_hash::{FxHashMap, FxHashSet};
 use rand::seq::SliceRandom;
+use rustc_hash::{FxHashMap, FxHashSet};
 
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::process::{Command, Stdio};
+use crate::restricted::Constraint;
 
+/// Heuristics choosing the variables merged by a round of equality restrictions
+#[derive(Clone, ValueEnum)]
+pub enum ContractionHeuristic {
+    /// Merges each vertex, in random order, with the neighbor it shares the most neighbors with
+    MaxDegMostCommon,
+    MinContractionDeg,
+}
 
-use crate::restricted::{Restriction, RestrictionOp};
-use crate::problem::Problem;
+impl ContractionHeuristic {
 
+    /// Returns the equalities of a round of contractions of the primal graph. Each vertex is in at
+    /// most one of them.
+    pub fn compute_restrictions(&self, primal_graph: &FxHashMap<usize, FxHashSet<usize>>) -> Vec<Constraint> {
+        match self {
+            Self::MaxDegMostCommon => {
+                let mut contraction_candidates = primal_graph.keys().copied().collect::<Vec<usize>>();
+                let mut contracted = FxHashSet::<usize>::default();
+                let mut rng = rand::rng();
+                contraction_candidates.shuffle(&mut rng);
+                let mut equiv = vec![];
+                for node in contraction_candidates.iter().copied() {
+                    if contracted.contains(&node) || primal_graph[&node].is_empty() {
+                        continue;
+                    }
+                    if let Some((_, contract_to)) = primal_graph[&node].iter().copied().filter(|n| !contracted.contains(n)).map(|n| (primal_graph[&node].intersection(&primal_graph[&n]).count(), n)).max() {
+                        contracted.insert(node);
+                        contracted.insert(contract_to);
+                        equiv.push(Constraint::equality(vec![node, contract_to], true));
+                    }
+                }
+                equiv
+            },
+            Self::MinContractionDeg => {
+                vec![]
+            },
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::io::{self, BufRead, Write};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A tree decomposition of a graph: bags of vertices connected by the edges of a tree. Each edge
+/// of the graph is in a bag, and the bags containing a vertex form a subtree.
+pub struct TreeDecomposition {
+    /// Vertices of each bag, sorted
+    bags: Vec<Vec<usize>>,
+    /// Edges of the tree, between the indexes of the bags
+    edges: Vec<(usize, usize)>,
+}
+
+/// Returns true if the two sorted lists have a common element
+fn intersects(a: &[usize], b: &[usize]) -> bool {
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => return true,
+        }
+    }
+    false
+}
+
+/// Parses the next token of the line as a number between 1 and `max`, and returns it starting at 0
+fn parse_index<'a>(tokens: &mut impl Iterator<Item = &'a str>, what: &str, max: usize, line: usize) -> Result<usize, String> {
+    match tokens.next().map(|token| token.parse::<usize>()) {
+        Some(Ok(number)) if number >= 1 && number <= max => Ok(number - 1),
+        Some(Ok(number)) => Err(format!("line {}: {} {} out of range", line, what, number)),
+        _ => Err(format!("line {}: expected a {}", line, what)),
+    }
+}
+
+fn find_root(parent: &mut [usize], mut x: usize) -> usize {
+    while parent[x] != x {
+        parent[x] = parent[parent[x]];
+        x = parent[x];
+    }
+    x
+}
+
+impl TreeDecomposition {
+
+    /// Builds the decomposition obtained by eliminating the vertices of the graph in the order. The
+    /// bag of a vertex contains it and its neighbors at the time it is eliminated, and is connected
+    /// to the bag of the first of these neighbors to be eliminated.
+    pub fn from_elimination_order(graph: &FxHashMap<usize, FxHashSet<usize>>, order: &[usize]) -> Self {
+        let mut graph = graph.clone();
+        let neighborhoods = order.iter().map(|vertex| {
+            let neighbors = graph.remove(vertex).unwrap_or_default();
+            for &neighbor in neighbors.iter() {
+                if let Some(adjacent) = graph.get_mut(&neighbor) {
+                    adjacent.remove(vertex);
+                    adjacent.extend(neighbors.iter().copied().filter(|&n| n != neighbor));
+                }
+            }
+            neighbors.into_iter().collect::<Vec<usize>>()
+        }).collect::<Vec<Vec<usize>>>();
+        let position = order.iter().enumerate().map(|(index, &vertex)| (vertex, index)).collect::<FxHashMap<usize, usize>>();
+        let mut bags = Vec::with_capacity(order.len());
+        let mut edges = Vec::with_capacity(order.len());
+        let mut previous_root = None;
+        for (index, (&vertex, mut bag)) in order.iter().zip(neighborhoods).enumerate() {
+            match bag.iter().filter_map(|neighbor| position.get(neighbor)).min() {
+                Some(&parent) => edges.push((index, parent)),
+                // The roots of the components of the graph are chained to form a single tree
+                None => {
+                    if let Some(root) = previous_root {
+                        edges.push((root, index));
+                    }
+                    previous_root = Some(index);
+                },
+            }
+            bag.push(vertex);
+            bag.sort_unstable();
+            bags.push(bag);
+        }
+        Self {
+            bags,
+            edges,
+        }
+    }
+
+    /// Returns the decomposition in which each vertex is replaced by its image, or removed if it has
+    /// none. The result is a decomposition of the image of the graph only if the vertices mapped to
+    /// the same image have connected bags, which `check` tells.
+    pub fn map_vertices(&self, image: impl Fn(usize) -> Option<usize>) -> Self {
+        let bags = self.bags.iter().map(|bag| {
+            let mut bag = bag.iter().filter_map(|&vertex| image(vertex)).collect::<Vec<usize>>();
+            bag.sort_unstable();
+            bag.dedup();
+            bag
+        }).collect();
+        Self {
+            bags,
+            edges: self.edges.clone(),
+        }
+    }
+
+    /// Returns the vertices of each bag, sorted
+    pub fn bags(&self) -> &[Vec<usize>] {
+        &self.bags
+    }
+
+    /// Replaces the vertex by `to` in the bag, or removes it from the bag if `to` is None
+    pub fn replace_in_bag(&mut self, bag: usize, vertex: usize, to: Option<usize>) {
+        let bag = &mut self.bags[bag];
+        if let Ok(index) = bag.binary_search(&vertex) {
+            bag.remove(index);
+        }
+        if let Some(to) = to && let Err(index) = bag.binary_search(&to) {
+            bag.insert(index, to);
+        }
+    }
+
+    /// Returns the parent of each bag in the tree rooted at the first bag, the root being its own
+    /// parent, and the depth of each bag
+    pub fn rooted_tree(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut neighbors = vec![vec![]; self.bags.len()];
+        for &(a, b) in self.edges.iter() {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+        }
+        let mut parent = (0..self.bags.len()).collect::<Vec<usize>>();
+        let mut depth = vec![0; self.bags.len()];
+        let mut visited = vec![false; self.bags.len()];
+        let mut stack = if self.bags.is_empty() { vec![] } else { vec![0] };
+        while let Some(bag) = stack.pop() {
+            visited[bag] = true;
+            for &neighbor in neighbors[bag].iter().filter(|&&neighbor| !visited[neighbor]) {
+                parent[neighbor] = bag;
+                depth[neighbor] = depth[bag] + 1;
+                stack.push(neighbor);
+            }
+        }
+        (parent, depth)
+    }
+
+    /// Replaces connected sets of bags by other decompositions. Each set is replaced by the bags of
+    /// its decomposition, and each bag adjacent to the set is attached to a bag of the decomposition
+    /// containing their separator, which must be a clique of the graph the decomposition was
+    /// computed on. The sets must not be adjacent.
+    pub fn replace_subtrees(&mut self, replacements: Vec<(Vec<usize>, TreeDecomposition)>) {
+        let mut replaced_by = vec![None; self.bags.len()];
+        for (index, (bags, _)) in replacements.iter().enumerate() {
+            for &bag in bags.iter() {
+                replaced_by[bag] = Some(index);
+            }
+        }
+        let mut index_of = vec![0; self.bags.len()];
+        let mut bags = vec![];
+        for (index, bag) in self.bags.iter().enumerate().filter(|&(index, _)| replaced_by[index].is_none()) {
+            index_of[index] = bags.len();
+            bags.push(bag.clone());
+        }
+        let mut offsets = Vec::with_capacity(replacements.len());
+        let mut edges = vec![];
+        for (_, mut decomposition) in replacements {
+            // An empty decomposition is given an empty bag to which the adjacent bags are attached
+            if decomposition.bags.is_empty() {
+                decomposition.bags.push(vec![]);
+            }
+            offsets.push((bags.len(), decomposition.bags.len()));
+            edges.extend(decomposition.edges.iter().map(|&(a, b)| (a + bags.len(), b + bags.len())));
+            bags.extend(decomposition.bags);
+        }
+        for &(a, b) in self.edges.iter() {
+            match (replaced_by[a], replaced_by[b]) {
+                (None, None) => edges.push((index_of[a], index_of[b])),
+                (Some(_), Some(_)) => {},
+                (Some(replacement), None) | (None, Some(replacement)) => {
+                    let (inner, outer) = if replaced_by[a].is_some() { (a, b) } else { (b, a) };
+                    let separator = self.bags[inner].iter().filter(|vertex| self.bags[outer].binary_search(vertex).is_ok()).collect::<Vec<&usize>>();
+                    let (offset, length) = offsets[replacement];
+                    let attachment = (offset..offset + length).find(|&bag| separator.iter().all(|vertex| bags[bag].binary_search(vertex).is_ok())).expect("separator in no bag of the replacement");
+                    edges.push((index_of[outer], attachment));
+                },
+            }
+        }
+        self.bags = bags;
+        self.edges = edges;
+    }
+
+    /// Returns the width of the decomposition: the size of its largest bag minus one
+    pub fn width(&self) -> usize {
+        self.bags.iter().map(|bag| bag.len()).max().unwrap_or(1).saturating_sub(1)
+    }
+
+    /// Checks that this is a tree decomposition of the graph: the bags form a tree, each vertex
+    /// and each edge of the graph are in a bag, and the bags containing a vertex are connected.
+    /// Vertices are reported starting at 1, as in the PACE format.
+    pub fn check(&self, graph: &FxHashMap<usize, FxHashSet<usize>>) -> Result<(), String> {
+        if !self.bags.is_empty() && self.edges.len() != self.bags.len() - 1 {
+            return Err(format!("{} edges between {} bags do not form a tree", self.edges.len(), self.bags.len()));
+        }
+        let mut parent = (0..self.bags.len()).collect::<Vec<usize>>();
+        for &(a, b) in self.edges.iter() {
+            if a >= self.bags.len() || b >= self.bags.len() {
+                return Err(format!("edge {} {} between unknown bags", a + 1, b + 1));
+            }
+            let (root_a, root_b) = (find_root(&mut parent, a), find_root(&mut parent, b));
+            if root_a == root_b {
+                return Err(format!("edge {} {} closes a cycle", a + 1, b + 1));
+            }
+            parent[root_a] = root_b;
+        }
+        let mut containing = FxHashMap::<usize, Vec<usize>>::default();
+        for (index, bag) in self.bags.iter().enumerate() {
+            for &vertex in bag.iter() {
+                containing.entry(vertex).or_default().push(index);
+            }
+        }
+        for (&vertex, neighbors) in graph.iter() {
+            let Some(bags) = containing.get(&vertex) else {
+                return Err(format!("vertex {} is in no bag", vertex + 1));
+            };
+            for &neighbor in neighbors.iter().filter(|&&neighbor| neighbor > vertex) {
+                if !containing.get(&neighbor).is_some_and(|neighbor_bags| intersects(bags, neighbor_bags)) {
+                    return Err(format!("edge {} {} is in no bag", vertex + 1, neighbor + 1));
+                }
+            }
+        }
+        // In a tree, the bags containing a vertex are connected if and only if there is one tree
+        // edge less than bags between them
+        let mut inner_edges = FxHashMap::<usize, usize>::default();
+        for &(a, b) in self.edges.iter() {
+            for vertex in self.bags[a].iter().filter(|vertex| self.bags[b].binary_search(vertex).is_ok()) {
+                *inner_edges.entry(*vertex).or_default() += 1;
+            }
+        }
+        for (vertex, bags) in containing.iter() {
+            if inner_edges.get(vertex).copied().unwrap_or(0) != bags.len() - 1 {
+                return Err(format!("the bags containing vertex {} are not connected", vertex + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the decomposition in the PACE .td format, for a graph with the given number of
+    /// vertices. Vertices and bags are numbered from 1.
+    pub fn write_pace(&self, writer: &mut dyn Write, number_vertices: usize) -> io::Result<()> {
+        writeln!(writer, "s td {} {} {}", self.bags.len(), self.bags.iter().map(|bag| bag.len()).max().unwrap_or(0), number_vertices)?;
+        for (index, bag) in self.bags.iter().enumerate() {
+            write!(writer, "b {}", index + 1)?;
+            for vertex in bag.iter() {
+                write!(writer, " {}", vertex + 1)?;
+            }
+            writeln!(writer)?;
+        }
+        for (a, b) in self.edges.iter() {
+            writeln!(writer, "{} {}", a + 1, b + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a decomposition in the PACE .td format. Returns it with the number of vertices of the
+    /// graph declared in the file.
+    pub fn read_pace(reader: impl BufRead) -> Result<(Self, usize), String> {
+        let mut header = None;
+        let mut bags = vec![];
+        let mut edges = vec![];
+        for (index, line) in reader.lines().enumerate() {
+            let line = line.map_err(|error| format!("line {}: {}", index + 1, error))?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                None | Some("c") => continue,
+                Some("s") => {
+                    if header.is_some() {
+                        return Err(format!("line {}: more than one 's td' line", index + 1));
+                    }
+                    if tokens.next() != Some("td") {
+                        return Err(format!("line {}: expected 's td'", index + 1));
+                    }
+                    let mut numbers = tokens.map(|token| token.parse::<usize>().ok());
+                    match (numbers.next().flatten(), numbers.next().flatten(), numbers.next().flatten(), numbers.next()) {
+                        (Some(number_bags), Some(max_bag_size), Some(number_vertices), None) => {
+                            bags = vec![None; number_bags];
+                            header = Some((number_bags, max_bag_size, number_vertices));
+                        },
+                        _ => return Err(format!("line {}: invalid 's td' line", index + 1)),
+                    }
+                },
+                Some("b") => {
+                    let Some((number_bags, max_bag_size, number_vertices)) = header else {
+                        return Err(format!("line {}: bag before the 's td' line", index + 1));
+                    };
+                    let bag = parse_index(&mut tokens, "bag", number_bags, index + 1)?;
+                    let mut vertices = vec![];
+                    for token in tokens {
+                        match token.parse::<usize>() {
+                            Ok(vertex) if vertex >= 1 && vertex <= number_vertices => vertices.push(vertex - 1),
+                            _ => return Err(format!("line {}: invalid vertex '{}'", index + 1, token)),
+                        }
+                    }
+                    vertices.sort_unstable();
+                    vertices.dedup();
+                    if vertices.len() > max_bag_size {
+                        return Err(format!("line {}: bag larger than the declared size {}", index + 1, max_bag_size));
+                    }
+                    if bags[bag].replace(vertices).is_some() {
+                        return Err(format!("line {}: bag {} declared twice", index + 1, bag + 1));
+                    }
+                },
+                Some(_) => {
+                    let Some((number_bags, _, _)) = header else {
+                        return Err(format!("line {}: edge before the 's td' line", index + 1));
+                    };
+                    let mut tokens = line.split_whitespace();
+                    let edge = (parse_index(&mut tokens, "bag", number_bags, index + 1)?, parse_index(&mut tokens, "bag", number_bags, index + 1)?);
+                    if tokens.next().is_some() {
+                        return Err(format!("line {}: invalid edge '{}'", index + 1, line.trim()));
+                    }
+                    edges.push(edge);
+                },
+            }
+        }
+        let Some((_, _, number_vertices)) = header else {
+            return Err("no 's td' line".to_string());
+        };
+        let bags = bags.into_iter().enumerate().map(|(index, bag)| bag.ok_or(format!("bag {} is missing", index + 1))).collect::<Result<Vec<Vec<usize>>, String>>()?;
+        Ok((Self { bags, edges }, number_vertices))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(number_vertices: usize, edges: &[(usize, usize)]) -> FxHashMap<usize, FxHashSet<usize>> {
+        let mut graph = (0..number_vertices).map(|vertex| (vertex, FxHashSet::default())).collect::<FxHashMap<usize, FxHashSet<usize>>>();
+        for &(a, b) in edges {
+            graph.get_mut(&a).unwrap().insert(b);
+            graph.get_mut(&b).unwrap().insert(a);
+        }
+        graph
+    }
+
+    #[test]
+    fn elimination_orders_give_decompositions_that_survive_the_pace_format() {
+        // A 3x4 grid, a cycle of 7 vertices and a clique of 5 vertices
+        let grid = (0..12).flat_map(|v| {
+            let right = (v % 4 != 3).then_some((v, v + 1));
+            let down = (v < 8).then_some((v, v + 4));
+            right.into_iter().chain(down)
+        }).collect::<Vec<(usize, usize)>>();
+        let cycle = (0..7).map(|v| (v, (v + 1) % 7)).collect::<Vec<(usize, usize)>>();
+        let clique = (0..5).flat_map(|u| (u + 1..5).map(move |v| (u, v))).collect::<Vec<(usize, usize)>>();
+        for (number_vertices, edges, row_major_width) in [(12, grid, 4), (7, cycle, 2), (5, clique, 4)] {
+            let graph = graph(number_vertices, &edges);
+            let row_major = (0..number_vertices).collect::<Vec<usize>>();
+            let interleaved = (0..number_vertices).step_by(2).chain((1..number_vertices).step_by(2)).collect::<Vec<usize>>();
+            let reversed = row_major.iter().rev().copied().collect::<Vec<usize>>();
+            for order in [row_major.clone(), interleaved, reversed] {
+                let decomposition = TreeDecomposition::from_elimination_order(&graph, &order);
+                assert_eq!(decomposition.check(&graph), Ok(()), "{:?}", order);
+                let mut pace = vec![];
+                decomposition.write_pace(&mut pace, number_vertices).unwrap();
+                let (read, read_vertices) = TreeDecomposition::read_pace(pace.as_slice()).unwrap();
+                assert_eq!(read_vertices, number_vertices);
+                assert_eq!((&read.bags, &read.edges), (&decomposition.bags, &decomposition.edges));
+                assert_eq!(read.width(), decomposition.width());
+            }
+            assert_eq!(TreeDecomposition::from_elimination_order(&graph, &row_major).width(), row_major_width);
+        }
+    }
+
+    #[test]
+    fn invalid_decompositions_are_rejected() {
+        let graph = graph(3, &[(0, 1), (1, 2)]);
+        let read = |pace: &str| TreeDecomposition::read_pace(pace.as_bytes()).map(|(decomposition, _)| decomposition);
+        assert_eq!(read("s td 2 2 3\nb 1 1 2\nb 2 2 3\n1 2\n").unwrap().check(&graph), Ok(()));
+        assert_eq!(read("s td 2 2 3\nb 1 1 2\nb 2 3\n1 2\n").unwrap().check(&graph), Err("edge 2 3 is in no bag".to_string()));
+        assert_eq!(read("s td 3 2 3\nb 1 1 2\nb 2 3\nb 3 2 3\n1 2\n2 3\n").unwrap().check(&graph), Err("the bags containing vertex 2 are not connected".to_string()));
+        assert_eq!(read("s td 2 2 3\nb 1 1 2\nb 2 2 3\n").unwrap().check(&graph), Err("0 edges between 2 bags do not form a tree".to_string()));
+        assert!(read("s td 1 1 3\nb 1 1 2\n").is_err());
+        assert!(read("s td 1 2 3\nb 1 1 4\n").is_err());
+        assert!(read("b 1 1 2\n").is_err());
+        assert!(read("s td 2 2 3\nb 1 1 2\n").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use std::collections::BTreeSet;
+
+/// Lower bounds on the treewidth of a graph
+pub struct TreewidthLowerBounds {
+    /// Largest minimum degree of the subgraphs obtained by deleting vertices of minimum degree
+    pub degeneracy: usize,
+    /// Largest minimum degree of the minors obtained by contracting a vertex of minimum degree
+    /// with its neighbor of minimum degree
+    pub minor_min_width: usize,
+    /// Largest minimum degree of the minors obtained by contracting a vertex of minimum degree
+    /// with the neighbor it has the fewest common neighbors with
+    pub mmd_plus: usize,
+}
+
+impl TreewidthLowerBounds {
+
+    /// Computes the lower bounds of the graph
+    pub fn new(graph: &FxHashMap<usize, FxHashSet<usize>>) -> Self {
+        Self {
+            degeneracy: minimum_degree_bound(graph, |_, _| None),
+            minor_min_width: minimum_degree_bound(graph, |graph, neighbors| {
+                neighbors.iter().copied().min_by_key(|&neighbor| (graph[&neighbor].len(), neighbor))
+            }),
+            mmd_plus: minimum_degree_bound(graph, |graph, neighbors| {
+                neighbors.iter().copied().min_by_key(|&neighbor| (graph[&neighbor].intersection(neighbors).count(), neighbor))
+            }),
+        }
+    }
+
+    /// Returns the best of the lower bounds
+    pub fn best(&self) -> usize {
+        self.degeneracy.max(self.minor_min_width).max(self.mmd_plus)
+    }
+}
+
+/// Repeatedly removes a vertex of minimum degree from the graph and returns the largest of these
+/// degrees, which is a lower bound on the treewidth. Given the other vertices and the neighbors of
+/// the removed vertex, `contract_into` returns the neighbor it is contracted into, if any, or
+/// None to delete it. Since the minors of a graph have a smaller treewidth, the bound holds for
+/// both operations.
+fn minimum_degree_bound(graph: &FxHashMap<usize, FxHashSet<usize>>, contract_into: impl Fn(&FxHashMap<usize, FxHashSet<usize>>, &FxHashSet<usize>) -> Option<usize>) -> usize {
+    let mut graph = graph.clone();
+    let mut queue = graph.iter().map(|(&vertex, neighbors)| (neighbors.len(), vertex)).collect::<BTreeSet<(usize, usize)>>();
+    let mut bound = 0;
+    while let Some((degree, vertex)) = queue.pop_first() {
+        bound = bound.max(degree);
+        let neighbors = graph.remove(&vertex).unwrap();
+        let target = contract_into(&graph, &neighbors);
+        for &neighbor in neighbors.iter() {
+            queue.remove(&(graph[&neighbor].len(), neighbor));
+        }
+        for &neighbor in neighbors.iter() {
+            let adjacent = graph.get_mut(&neighbor).unwrap();
+            adjacent.remove(&vertex);
+            if let Some(target) = target && neighbor != target {
+                adjacent.insert(target);
+                graph.get_mut(&target).unwrap().insert(neighbor);
+            }
+        }
+        for &neighbor in neighbors.iter() {
+            queue.insert((graph[&neighbor].len(), neighbor));
+        }
+    }
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::parse_dimacs;
+    use crate::problem::Problem;
+    use crate::tree_decomposition::{PrimalGraph, TreeDecomposition};
+    use crate::tree_decomposition::td_heuristic::EliminationHeuristic;
+
+    /// Returns the primal graph of a formula with a binary clause for each edge
+    fn graph(number_vertices: usize, edges: impl IntoIterator<Item = (usize, usize)>) -> PrimalGraph {
+        let clauses = edges.into_iter().map(|(a, b)| format!("{} {} 0\n", a + 1, b + 1)).collect::<Vec<String>>();
+        let dimacs = format!("p cnf {} {}\n{}", number_vertices, clauses.len(), clauses.concat());
+        PrimalGraph::new(&Problem::from_cnf(parse_dimacs(dimacs.as_bytes()).unwrap(), (0..number_vertices).collect(), Count::one(), false))
+    }
+
+    #[test]
+    fn bounds_do_not_exceed_the_treewidth() {
+        let petersen = (0..5).flat_map(|v| [(v, (v + 1) % 5), (v, v + 5), (v + 5, (v + 2) % 5 + 5)]);
+        let grid = (0..25).flat_map(|v| [(v % 5 != 4).then_some((v, v + 1)), (v < 20).then_some((v, v + 5))]).flatten();
+        // Graphs with their treewidth, and whether the minor bounds are known to reach it
+        let graphs = [
+            (graph(15, (1..15).map(|v| ((v - 1) / 2, v))), 1, true),
+            (graph(8, (0..8).map(|v| (v, (v + 1) % 8))), 2, true),
+            (graph(6, (0..6).flat_map(|u| (u + 1..6).map(move |v| (u, v)))), 5, true),
+            (graph(10, petersen), 4, false),
+            (graph(25, grid), 5, false),
+        ];
+        for (graph, treewidth, exact) in graphs.iter() {
+            let bounds = TreewidthLowerBounds::new(graph.adjacency());
+            assert!(bounds.degeneracy <= bounds.best() && bounds.minor_min_width <= bounds.best() && bounds.mmd_plus <= bounds.best());
+            assert!(bounds.best() <= *treewidth, "bound {} above treewidth {}", bounds.best(), treewidth);
+            if *exact {
+                assert_eq!(bounds.best(), *treewidth);
+            }
+            for heuristic in EliminationHeuristic::value_variants() {
+                let order = heuristic.elimination_order(graph, &FxHashSet::default(), None).unwrap();
+                assert!(bounds.best() <= TreeDecomposition::from_elimination_order(graph.adjacency(), &order).width());
+            }
+        }
+    }
+}
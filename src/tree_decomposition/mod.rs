@@ -1,5 +1,14 @@
 pub mod td;
 pub mod contraction;
+pub mod decomposition;
+pub mod lower_bound;
+pub mod primal_graph;
+pub mod solver;
+pub mod td_heuristic;
 
 pub use contraction::ContractionHeuristic;
-pub use td::compute_restrictions;
+pub use decomposition::TreeDecomposition;
+pub use primal_graph::PrimalGraph;
+pub use solver::read_tree_decomposition;
+pub use td_heuristic::EliminationHeuristic;
+pub use td::{compute_restrictions, write_tree_decomposition};
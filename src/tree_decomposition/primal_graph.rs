@@ -0,0 +1,359 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::problem::Problem;
+use super::TreeDecomposition;
+use super::td_heuristic::fill_in_score;
+
+/// Returns the variables of the clause, sorted and without duplicates
+fn clause_vertices(clause: &[isize]) -> Vec<usize> {
+    let mut vertices = clause.iter().map(|l| l.unsigned_abs() - 1).collect::<Vec<usize>>();
+    vertices.sort_unstable();
+    vertices.dedup();
+    vertices
+}
+
+/// The primal graph of a problem, updated along the restrictions instead of being rebuilt from
+/// the clauses at each round.
+///
+/// The graph follows the clauses modified by the restrictions and the simplifications: an edge is
+/// kept as long as an active clause contains its ends, and a vertex as long as it has an edge. The
+/// graph is thus the primal graph of the restricted problem, on which the treewidth lower bounds
+/// are valid.
+///
+/// The fill-in score of each vertex is kept up to date through the updates, as well as the bags of
+/// the last decomposition attached to the graph.
+pub struct PrimalGraph {
+    adjacency: FxHashMap<usize, FxHashSet<usize>>,
+    /// Number of missing edges between the neighbors of each vertex
+    fill_in: FxHashMap<usize, usize>,
+    /// Decomposition of the graph, with the bags containing each vertex, if one is attached
+    decomposition: Option<(TreeDecomposition, FxHashMap<usize, Vec<usize>>)>,
+    /// Vertices of each clause at the last update, empty for the inactive clauses
+    clause_vertices: Vec<Vec<usize>>,
+    /// Number of active clauses containing the ends of each edge, smallest end first
+    edge_clauses: FxHashMap<(usize, usize), usize>,
+    /// Position in the trail of the problem at the last update
+    trail_position: usize,
+}
+
+impl PrimalGraph {
+
+    /// Builds the primal graph of the active clauses of the problem
+    pub fn new(problem: &Problem) -> Self {
+        let mut adjacency = FxHashMap::<usize, FxHashSet<usize>>::default();
+        let mut edge_clauses = FxHashMap::<(usize, usize), usize>::default();
+        let clause_vertices = (0..problem.number_clauses()).map(|index| {
+            if !problem.is_clause_active(index) {
+                return vec![];
+            }
+            let vertices = clause_vertices(problem.clause_at(index));
+            for i in 0..vertices.len() {
+                for j in (i+1)..vertices.len() {
+                    let (u, v) = (vertices[i], vertices[j]);
+                    *edge_clauses.entry((u, v)).or_default() += 1;
+                    adjacency.entry(u).or_default().insert(v);
+                    adjacency.entry(v).or_default().insert(u);
+                }
+            }
+            vertices
+        }).collect();
+        let fill_in = adjacency.keys().map(|&vertex| (vertex, fill_in_score(&adjacency, vertex))).collect();
+        Self {
+            adjacency,
+            fill_in,
+            decomposition: None,
+            clause_vertices,
+            edge_clauses,
+            trail_position: problem.trail_position(),
+        }
+    }
+
+    /// Builds a graph from the neighbors of each vertex. It can not follow the restrictions of a
+    /// problem.
+    pub fn from_adjacency(adjacency: FxHashMap<usize, FxHashSet<usize>>) -> Self {
+        let fill_in = adjacency.keys().map(|&vertex| (vertex, fill_in_score(&adjacency, vertex))).collect();
+        Self {
+            adjacency,
+            fill_in,
+            decomposition: None,
+            clause_vertices: vec![],
+            edge_clauses: FxHashMap::default(),
+            trail_position: 0,
+        }
+    }
+
+    /// Returns the neighbors of each vertex
+    pub fn adjacency(&self) -> &FxHashMap<usize, FxHashSet<usize>> {
+        &self.adjacency
+    }
+
+    /// Returns the number of missing edges between the neighbors of the vertex
+    pub fn fill_in(&self, vertex: usize) -> usize {
+        self.fill_in[&vertex]
+    }
+
+    /// Attaches the decomposition of the graph, which is then updated with the contractions
+    pub fn attach_decomposition(&mut self, decomposition: TreeDecomposition) {
+        let mut containing = FxHashMap::<usize, Vec<usize>>::default();
+        for (index, bag) in decomposition.bags().iter().enumerate() {
+            for &vertex in bag.iter() {
+                containing.entry(vertex).or_default().push(index);
+            }
+        }
+        self.decomposition = Some((decomposition, containing));
+    }
+
+    /// Returns the width of the attached decomposition, if it is still a decomposition of the
+    /// graph
+    pub fn decomposition_width(&self) -> Option<usize> {
+        self.decomposition.as_ref().map(|(decomposition, _)| decomposition.width())
+    }
+
+    /// Updates the graph with the clauses modified since the graph has been built or last updated.
+    /// The fill-in scores are recomputed for the ends of the edges added or removed, whose
+    /// neighborhoods change, and updated for the other common neighbors of these ends. The attached
+    /// decomposition is updated with `decompose`, which computes the decomposition of a graph.
+    pub fn apply_restrictions(&mut self, problem: &Problem, decompose: impl Fn(&PrimalGraph) -> TreeDecomposition) {
+        let mut modified = problem.clauses_modified_since(self.trail_position).into_iter().collect::<Vec<usize>>();
+        modified.sort_unstable();
+        self.trail_position = problem.trail_position();
+        self.clause_vertices.resize(problem.number_clauses(), vec![]);
+        // Whether each edge of the modified clauses was in the graph before the update
+        let mut was_edge = FxHashMap::<(usize, usize), bool>::default();
+        for index in modified {
+            let vertices = if problem.is_clause_active(index) { clause_vertices(problem.clause_at(index)) } else { vec![] };
+            let previous = std::mem::replace(&mut self.clause_vertices[index], vertices);
+            for (vertices, added) in [(&previous, false), (&self.clause_vertices[index], true)] {
+                for i in 0..vertices.len() {
+                    for j in (i+1)..vertices.len() {
+                        let edge = (vertices[i], vertices[j]);
+                        let count = self.edge_clauses.entry(edge).or_default();
+                        was_edge.entry(edge).or_insert(*count > 0);
+                        if added { *count += 1 } else { *count -= 1 }
+                    }
+                }
+            }
+        }
+        let mut changed_edges = vec![];
+        for (edge, was_edge) in was_edge {
+            let is_edge = self.edge_clauses[&edge] > 0;
+            if !is_edge {
+                self.edge_clauses.remove(&edge);
+            }
+            if is_edge != was_edge {
+                changed_edges.push((edge, is_edge));
+            }
+        }
+        let mut changed = FxHashSet::<usize>::default();
+        for &((u, v), added) in changed_edges.iter() {
+            if added {
+                self.adjacency.entry(u).or_default().insert(v);
+                self.adjacency.entry(v).or_default().insert(u);
+            } else {
+                self.adjacency.get_mut(&u).unwrap().remove(&v);
+                self.adjacency.get_mut(&v).unwrap().remove(&u);
+            }
+            changed.insert(u);
+            changed.insert(v);
+        }
+        for &((u, v), added) in changed_edges.iter() {
+            if let (Some(u_neighbors), Some(v_neighbors)) = (self.adjacency.get(&u), self.adjacency.get(&v)) {
+                for common in u_neighbors.intersection(v_neighbors).filter(|common| !changed.contains(common)) {
+                    let fill_in = self.fill_in.get_mut(common).unwrap();
+                    if added { *fill_in -= 1 } else { *fill_in += 1 }
+                }
+            }
+        }
+        for &vertex in changed.iter() {
+            if self.adjacency[&vertex].is_empty() {
+                self.adjacency.remove(&vertex);
+                self.fill_in.remove(&vertex);
+            } else {
+                self.fill_in.insert(vertex, fill_in_score(&self.adjacency, vertex));
+            }
+        }
+        self.update_decomposition(problem, changed, decompose);
+    }
+
+    /// Updates the attached decomposition after an update of the graph, in which `changed` are the
+    /// ends of the edges added or removed.
+    ///
+    /// The substituted vertices are first replaced in the bags by the vertices replacing them, and
+    /// the vertices that left the graph are removed from the bags. The bags containing the changed
+    /// vertices, with the bags connecting them, are then eliminated again: each connected region of
+    /// the tree is replaced by a decomposition, computed with `decompose`, of the graph induced by
+    /// its vertices in which the separators with the adjacent bags are cliques, so that these bags
+    /// can be attached to it. If the contracted bags are still a decomposition of the graph, a
+    /// region is only replaced when its new decomposition is narrower.
+    fn update_decomposition(&mut self, problem: &Problem, mut changed: FxHashSet<usize>, decompose: impl Fn(&PrimalGraph) -> TreeDecomposition) {
+        let Some((mut decomposition, mut containing)) = self.decomposition.take() else {
+            return;
+        };
+        // True while the contracted bags are a decomposition of the graph
+        let mut valid = true;
+        let mut vertices = containing.keys().copied().collect::<Vec<usize>>();
+        vertices.sort_unstable();
+        for vertex in vertices {
+            let repr = problem.literal_repr(vertex as isize + 1).unsigned_abs() - 1;
+            let removed = problem.literal_value(vertex as isize + 1).is_some() || !self.adjacency.contains_key(&repr);
+            if !removed && repr == vertex {
+                continue;
+            }
+            let from_bags = containing.remove(&vertex).unwrap_or_default();
+            for &bag in from_bags.iter() {
+                decomposition.replace_in_bag(bag, vertex, (!removed).then_some(repr));
+            }
+            if !removed {
+                changed.insert(repr);
+                let into_bags = containing.entry(repr).or_default();
+                // The bags containing `vertex` and its representative stay connected once merged
+                // only if they share a bag
+                valid &= from_bags.is_empty() || into_bags.is_empty() || from_bags.iter().any(|bag| into_bags.contains(bag));
+                for bag in from_bags {
+                    if !into_bags.contains(&bag) {
+                        into_bags.push(bag);
+                    }
+                }
+            }
+        }
+        if decomposition.bags().is_empty() {
+            let decomposition = decompose(self);
+            self.attach_decomposition(decomposition);
+            return;
+        }
+        let (parent, depth) = decomposition.rooted_tree();
+        let mut region = vec![false; decomposition.bags().len()];
+        let mut connect = |mut a: usize, mut b: usize| {
+            region[a] = true;
+            region[b] = true;
+            while a != b {
+                if depth[a] >= depth[b] { a = parent[a] } else { b = parent[b] }
+                region[a] = true;
+                region[b] = true;
+            }
+        };
+        let mut changed = changed.into_iter().filter(|vertex| self.adjacency.contains_key(vertex)).collect::<Vec<usize>>();
+        changed.sort_unstable();
+        let bags_of = |vertex: &usize| containing.get(vertex).filter(|bags| !bags.is_empty());
+        // The changed vertices in no bag, which are added to the region of a bag of their neighbors
+        let mut missing = FxHashSet::<usize>::default();
+        for vertex in changed {
+            let Some(bags) = bags_of(&vertex) else {
+                missing.insert(vertex);
+                continue;
+            };
+            for &bag in bags.iter() {
+                connect(bags[0], bag);
+            }
+            for neighbor in self.adjacency[&vertex].iter() {
+                if let Some(neighbor_bags) = bags_of(neighbor) && !neighbor_bags.iter().any(|bag| bags.contains(bag)) {
+                    valid = false;
+                    connect(bags[0], neighbor_bags[0]);
+                }
+            }
+        }
+        let mut added = FxHashMap::<usize, Vec<usize>>::default();
+        let mut missing_vertices = missing.iter().copied().collect::<Vec<usize>>();
+        missing_vertices.sort_unstable();
+        for vertex in missing_vertices {
+            if !missing.remove(&vertex) {
+                continue;
+            }
+            valid = false;
+            // The connected missing vertices go together, in the region of the bags of their
+            // neighbors, or of the root if they have none
+            let mut group = vec![vertex];
+            let mut anchors = vec![];
+            let mut index = 0;
+            while index < group.len() {
+                for neighbor in self.adjacency[&group[index]].iter() {
+                    if missing.remove(neighbor) {
+                        group.push(*neighbor);
+                    } else if let Some(bags) = bags_of(neighbor) {
+                        anchors.push(bags[0]);
+                    }
+                }
+                index += 1;
+            }
+            let anchor = anchors.first().copied().unwrap_or(0);
+            for &other in anchors.iter() {
+                connect(anchor, other);
+            }
+            connect(anchor, anchor);
+            added.entry(anchor).or_default().extend(group);
+        }
+        // Connected regions of the tree, each one being numbered by its bag closest to the root
+        let mut order = (0..region.len()).filter(|&bag| region[bag]).collect::<Vec<usize>>();
+        order.sort_by_key(|&bag| depth[bag]);
+        let mut region_of = vec![usize::MAX; region.len()];
+        let mut regions = Vec::<Vec<usize>>::new();
+        for bag in order {
+            if parent[bag] != bag && region[parent[bag]] {
+                region_of[bag] = region_of[parent[bag]];
+            } else {
+                region_of[bag] = regions.len();
+                regions.push(vec![]);
+            }
+            regions[region_of[bag]].push(bag);
+        }
+        // Graph induced by the vertices of each region, in which the separators with the adjacent
+        // bags are cliques
+        let mut locals = regions.iter().map(|bags| {
+            let mut vertices = bags.iter().flat_map(|&bag| decomposition.bags()[bag].iter().copied()).collect::<FxHashSet<usize>>();
+            vertices.extend(bags.iter().filter_map(|bag| added.get(bag)).flatten().copied());
+            vertices.iter().map(|&vertex| {
+                (vertex, self.adjacency[&vertex].iter().copied().filter(|neighbor| vertices.contains(neighbor)).collect::<FxHashSet<usize>>())
+            }).collect::<FxHashMap<usize, FxHashSet<usize>>>()
+        }).collect::<Vec<FxHashMap<usize, FxHashSet<usize>>>>();
+        for bag in (0..parent.len()).filter(|&bag| region_of[bag] != region_of[parent[bag]]) {
+            let separator = decomposition.bags()[bag].iter().copied().filter(|vertex| decomposition.bags()[parent[bag]].binary_search(vertex).is_ok()).collect::<Vec<usize>>();
+            let index = if region[bag] { region_of[bag] } else { region_of[parent[bag]] };
+            for &u in separator.iter() {
+                locals[index].get_mut(&u).unwrap().extend(separator.iter().copied().filter(|&v| v != u));
+            }
+        }
+        let mut replacements = vec![];
+        for (bags, local) in regions.into_iter().zip(locals) {
+            let local_decomposition = decompose(&PrimalGraph::from_adjacency(local));
+            let width = bags.iter().map(|&bag| decomposition.bags()[bag].len()).max().unwrap_or(1).saturating_sub(1);
+            if !valid || local_decomposition.width() < width {
+                replacements.push((bags, local_decomposition));
+            }
+        }
+        log::trace!("Re-eliminating {} regions of the tree decomposition", replacements.len());
+        decomposition.replace_subtrees(replacements);
+        self.attach_decomposition(decomposition);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::parse_dimacs;
+
+    #[test]
+    fn updated_graph_and_decomposition_follow_the_restrictions() {
+        let cnf = parse_dimacs("p cnf 6 5\n1 2 3 0\n-1 4 0\n2 -4 5 0\n3 5 6 0\n-2 -6 0\n".as_bytes()).unwrap();
+        let mut problem = Problem::from_cnf(cnf, (0..6).collect(), Count::one(), false);
+        let mut graph = PrimalGraph::new(&problem);
+        let order = (0..6).collect::<Vec<usize>>();
+        graph.attach_decomposition(TreeDecomposition::from_elimination_order(graph.adjacency(), &order));
+        problem.new_level();
+        problem.make_equal(0, 3);
+        problem.make_not_equal(1, 5);
+        problem.simplify();
+        graph.apply_restrictions(&problem, |graph| {
+            let mut order = graph.adjacency().keys().copied().collect::<Vec<usize>>();
+            order.sort_unstable();
+            TreeDecomposition::from_elimination_order(graph.adjacency(), &order)
+        });
+        let rebuilt = PrimalGraph::new(&problem);
+        assert_eq!(graph.adjacency(), rebuilt.adjacency());
+        for &vertex in rebuilt.adjacency().keys() {
+            assert_eq!(graph.fill_in(vertex), rebuilt.fill_in(vertex));
+        }
+        let (decomposition, _) = graph.decomposition.as_ref().unwrap();
+        assert_eq!(decomposition.check(graph.adjacency()), Ok(()));
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::Stdio;
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::supervisor::{Outcome, Supervised};
+use crate::tools;
+use super::TreeDecomposition;
+
+/// Seconds given to the solver to print its best decomposition once its time limit is reached
+const GRACE_PERIOD: u64 = 5;
+
+/// Reads the tree decomposition of a PACE .td file
+pub fn read_tree_decomposition(path: &Path) -> Result<TreeDecomposition, String> {
+    let file = File::open(path).map_err(|error| error.to_string())?;
+    TreeDecomposition::read_pace(BufReader::new(file)).map(|(decomposition, _)| decomposition)
+}
+
+/// Writes the graph in the PACE .gr format. The vertices are numbered densely, in increasing
+/// order; returns the vertex of each number.
+fn write_graph(graph: &FxHashMap<usize, FxHashSet<usize>>, writer: &mut dyn Write) -> io::Result<Vec<usize>> {
+    let mut vertices = graph.keys().copied().collect::<Vec<usize>>();
+    vertices.sort_unstable();
+    let number = vertices.iter().enumerate().map(|(index, &vertex)| (vertex, index + 1)).collect::<FxHashMap<usize, usize>>();
+    let number_edges = graph.values().map(|neighbors| neighbors.len()).sum::<usize>() / 2;
+    writeln!(writer, "p tw {} {}", vertices.len(), number_edges)?;
+    for &vertex in vertices.iter() {
+        for neighbor in graph[&vertex].iter().filter(|&&neighbor| neighbor > vertex) {
+            writeln!(writer, "{} {}", number[&vertex], number[neighbor])?;
+        }
+    }
+    Ok(vertices)
+}
+
+/// Computes a tree decomposition of the graph with an external solver following the PACE
+/// conventions: it reads the graph on its standard input and prints the decomposition on its
+/// standard output. At the time limit, anytime solvers are asked to print their best decomposition
+/// with SIGTERM. Returns None if the solver gives no decomposition.
+pub fn solve(solver: &str, graph: &FxHashMap<usize, FxHashSet<usize>>, timeout: u64) -> Option<TreeDecomposition> {
+    // The graph is written in a private temporary file, removed when it is dropped
+    let written = tempfile::Builder::new().prefix("couaincre-").suffix(".gr").tempfile().and_then(|input| {
+        let mut writer = BufWriter::new(input.as_file());
+        let vertices = write_graph(graph, &mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        Ok((input, vertices))
+    });
+    let (input, vertices) = match written {
+        Ok(written) => written,
+        Err(error) => {
+            log::warn!("Can not write the input of {}: {}", solver, error);
+            return None;
+        },
+    };
+    log::trace!("Launching {} on a graph with {} vertices with {} seconds timeout", solver, vertices.len(), timeout);
+    let mut command = tools::command(solver);
+    command.stdin(input.reopen().map(Stdio::from).unwrap_or(Stdio::null()));
+    let outcome = Supervised::new(solver, command).timeout(timeout).grace_period(GRACE_PERIOD).run(|_, output| {
+        match TreeDecomposition::read_pace(output.as_bytes()) {
+            Ok((decomposition, _)) => Some(decomposition),
+            Err(error) => {
+                log::debug!("Invalid tree decomposition from {}: {}", solver, error);
+                None
+            },
+        }
+    });
+    match outcome {
+        Outcome::Solved(decomposition) => Some(decomposition.map_vertices(|number| vertices.get(number).copied())),
+        _ => None,
+    }
+}
@@ -1,148 +1,232 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
 use rustc_hash::{FxHashSet, FxHashMap};
 
 use crate::Args;
-use crate::problem::Problem;
-use crate::restricted::Restriction;
+use crate::problem::{InputVariable, Problem};
+use crate::restricted::Constraint;
+use super::{PrimalGraph, TreeDecomposition, solver};
+use super::lower_bound::TreewidthLowerBounds;
+use super::td_heuristic::best_decomposition;
 
-fn fill_in_score(graph: &FxHashMap<usize, FxHashSet<usize>>, node: usize) -> usize {
-    if graph[&node].is_empty() {
-        return 0;
-    }
-    let number_neighbors = graph[&node].len();
-    let mut missing_edges = (number_neighbors * (number_neighbors - 1)) / 2;
-    let neighbors = graph[&node].iter().copied().collect::<Vec<usize>>();
-    for i in 0..neighbors.len() {
-        for j in (i+1)..neighbors.len() {
-            if graph[&neighbors[i]].contains(&neighbors[j]) {
-                missing_edges -= 1;
-            }
-        }
-    }
-    missing_edges
+/// Returns the deadline of the elimination heuristics after the first one
+fn heuristics_deadline(args: &Args) -> Instant {
+    Instant::now() + Duration::from_secs(args.td_heuristics_timeout)
 }
 
-fn compute_treewidth(mut graph: FxHashMap<usize, FxHashSet<usize>>) -> usize {
-    let number_var = graph.len();
-
-    // Buckets used to compute the order. We place each node in a bucket corresponding to its
-    // heuristic score. Then, we can process nodes in increasing order of bucket.
-    let mut buckets = FxHashMap::<usize, Vec<usize>>::default();
-    // Flag to indicate if the score of a node must be recomputed. We lazily recompute them
-    // when poping them from the bucket as this computation can be long (e.g., min-fill)
-    //
-    // /!\ Note: since we lazily recompute the score when popping nodes from the bucket, we
-    // might process nodes in non-greedy order (i.e., process a node that has a worst-score
-    // than another non-processed node). However, re-computing the heuristic at each
-    // modification of the primal graph is not scalable for large graphs.
-    //
-    let mut recompute_score = FxHashSet::<usize>::default();
-    // Current minimum score. Since we greedily select nodes based on their minimum score, this
-    // give the next bucket to select a node from.
-    let mut min_score = usize::MAX;
-    // Initialise the buckets
-    for (candidate, _) in graph.iter() {
-        let score = fill_in_score(&graph, *candidate);
-        min_score = min_score.min(score);
-        insert_in_bucket(&mut buckets, score, *candidate);
-    }
-    // We compute the order for each node.
-    let mut eliminated = 0;
-    let mut treewidth = 0;
-    while eliminated != number_var {
-        // Finds the next non-empty bucket
-        while !buckets.contains_key(&min_score) || buckets.get(&min_score).unwrap().is_empty() {
-            min_score += 1;
-        }
-        // Pop a node from the bucket and recompute its score if needed. If the new score is
-        // worst than the computed one, put it in the associated bucket.
-        let node = buckets.get_mut(&min_score).unwrap().pop().unwrap();
-        if recompute_score.contains(&node) {
-            recompute_score.remove(&node);
-            let new_score = fill_in_score(&graph, node);
-            if new_score > min_score {
-                insert_in_bucket(&mut buckets, new_score, node);
-                continue;
-            }
-            min_score = min_score.min(new_score);
+/// Computes a tree decomposition of the graph with the elimination heuristics of the arguments,
+/// the ones after the first being abandoned at the deadline
+fn compute_tree_decomposition(args: &Args, problem: &Problem, graph: &PrimalGraph, deadline: Instant) -> TreeDecomposition {
+    let projection = problem.iter_independent_set().collect::<FxHashSet<usize>>();
+    best_decomposition(&args.td_heuristics, graph, &projection, deadline)
+}
+
+/// Attaches to the graph the best valid decomposition among the `--td-file` decomposition and the
+/// one of the `--td-solver` solver, or the decomposition of the elimination heuristics if there is
+/// none, and returns its width. The variables of the `--td-file` decomposition, which are the ones
+/// of the input formula, are replaced by their representative in the restricted problem, and the
+/// ones that are not in the graph are removed.
+fn update_decomposition(args: &Args, problem: &Problem, graph: &mut PrimalGraph) -> usize {
+    let mut best: Option<TreeDecomposition> = None;
+    let mut keep_best = |decomposition: TreeDecomposition| {
+        if best.as_ref().is_none_or(|best| decomposition.width() < best.width()) {
+            best = Some(decomposition);
         }
-        eliminated += 1;
-
-        // Clique size is primal_graph[node] + 1 (the neighbors and the node) but we remove 1 for
-        // the treewidth.
-        treewidth = treewidth.max(graph[&node].len());
-        
-        // Apply the node elimination
-
-        // Remove node from the graph (disconnect it from its neighbors) and connect all of its
-        // neighbors
-        let neighbors = graph[&node].iter().copied().collect::<Vec<usize>>();
-        for neighbor in neighbors.iter().copied() {
-            graph.get_mut(&neighbor).unwrap().extend(neighbors.iter().copied().filter(|n| *n != neighbor));
-            graph.get_mut(&neighbor).unwrap().remove(&node);
+    };
+    if let Some(decomposition) = args.td_file_decomposition() {
+        let decomposition = decomposition.map_vertices(|vertex| {
+            let InputVariable::Literal(repr) = (vertex < problem.number_input_var()).then(|| problem.input_variable_status(vertex))? else {
+                return None;
+            };
+            let repr = repr.unsigned_abs() - 1;
+            graph.adjacency().contains_key(&repr).then_some(repr)
+        });
+        match decomposition.check(graph.adjacency()) {
+            Ok(()) => keep_best(decomposition),
+            Err(error) => log::debug!("The tree decomposition of the file does not fit the primal graph: {}", error),
         }
-        // Flags node for which the heuristic needs to be recomputed
-        // All nodes at a distance of 2 in the graph can have their min-fill heuristic
-        // changed.
-        for neighbor in graph[&node].iter().copied() {
-            recompute_score.insert(neighbor);
-            for neighbor_of_neighbor in graph[&neighbor].iter().copied().filter(|n| *n != node) {
-                recompute_score.insert(neighbor_of_neighbor);
-            }
+    }
+    if let Some(name) = args.td_solver.as_deref() && !graph.adjacency().is_empty() {
+        match solver::solve(name, graph.adjacency(), args.td_solver_timeout).map(|decomposition| decomposition.check(graph.adjacency()).map(|_| decomposition)) {
+            Some(Ok(decomposition)) => keep_best(decomposition),
+            Some(Err(error)) => log::debug!("The tree decomposition of {} does not fit the primal graph: {}", name, error),
+            None => log::debug!("{} gave no tree decomposition", name),
         }
-        graph.get_mut(&node).unwrap().clear();
     }
-    treewidth
+    let decomposition = best.unwrap_or_else(|| compute_tree_decomposition(args, problem, graph, heuristics_deadline(args)));
+    let width = decomposition.width();
+    graph.attach_decomposition(decomposition);
+    width
 }
 
-fn compute_primal_graph(problem: &Problem) -> FxHashMap<usize, FxHashSet<usize>> {
-    let mut graph = FxHashMap::<usize, FxHashSet<usize>>::default();
-    for clause in problem.iter_clauses() {
-        for i in 0..clause.len() {
-            for j in (i+1)..clause.len() {
-                let u = clause[i].unsigned_abs() - 1;
-                let v = clause[j].unsigned_abs() - 1;
-                graph.entry(u).or_default().insert(v);
-                graph.entry(v).or_default().insert(u);
-            }
-        }
+/// Computes the lower bounds on the treewidth of the graph and logs them. Returns the best one.
+fn lower_bound(graph: &FxHashMap<usize, FxHashSet<usize>>) -> usize {
+    let bounds = TreewidthLowerBounds::new(graph);
+    log::debug!("Treewidth lower bounds: degeneracy {}, minor-min-width {}, MMD+ {}", bounds.degeneracy, bounds.minor_min_width, bounds.mmd_plus);
+    bounds.best()
+}
+
+/// Returns the number of rounds, at the pace at which `rounds` rounds decreased the width from
+/// `initial` to `current`, that it would take in total for the width to reach the threshold, or
+/// None if the width did not decrease
+fn expected_rounds(initial: usize, current: usize, threshold: usize, rounds: usize) -> Option<usize> {
+    if current <= threshold {
+        return Some(rounds);
     }
-    graph
+    let progress = initial.saturating_sub(current);
+    ((current - threshold) * rounds).checked_div(progress).map(|remaining| rounds + remaining + 1)
 }
 
-pub fn compute_restrictions(args: &Args, mut problem: Problem) -> Vec<Restriction> {
+/// Computes equality restrictions until the treewidth of the restricted problem is below the
+/// threshold. Each round of restrictions is applied on the problem in its own restriction level,
+/// and all of them are undone before returning.
+///
+/// The contraction stops after `td_max_rounds` rounds, or earlier when, at the pace at which the
+/// rounds decrease both the treewidth and its lower bound, it would take more than `td_max_rounds`
+/// rounds in total for them to reach the threshold. The restrictions of the rounds done so far are
+/// returned, except the ones of a round that made the problem UNSAT.
+pub fn compute_restrictions(args: &Args, problem: &mut Problem) -> Vec<Constraint> {
     log::trace!("Computing restrictions for lower bound computation");
-    let mut primal_graph = compute_primal_graph(&problem);
+    let mut primal_graph = PrimalGraph::new(problem);
 
-    let mut treewidth = compute_treewidth(primal_graph.clone());
-    log::info!("Initial treewidth is {}", treewidth);
+    let mut treewidth = update_decomposition(args, problem, &mut primal_graph);
+    let initial_treewidth = treewidth;
+    let initial_lower_bound = lower_bound(primal_graph.adjacency());
+    log::info!("Initial treewidth is {} (lower bound {})", treewidth, initial_lower_bound);
     if treewidth <= args.td_threshold {
         return vec![];
     }
     let mut restrictions = vec![];
+    let start_level = problem.restriction_level();
+    let mut rounds = 0;
 
     while treewidth > args.td_threshold {
+        if rounds >= args.td_max_rounds {
+            log::warn!("The treewidth {} did not reach {} within {} rounds, stopping the contraction", treewidth, args.td_threshold, args.td_max_rounds);
+            break;
+        }
 
-        let mut local_restrictions = args.contraction_heuristic.compute_restrictions(&primal_graph, &problem);
+        let mut local_restrictions = args.contraction_heuristic.compute_restrictions(primal_graph.adjacency());
+        if local_restrictions.is_empty() {
+            log::warn!("The contraction heuristic gives no more restrictions, stopping the contraction");
+            break;
+        }
+        let previous_length = restrictions.len();
+        problem.new_level();
 
         while let Some(restriction) = local_restrictions.pop() {
-            let vars = restriction.vars();
-            let x = vars[0];
-            for y in vars.iter().skip(1).copied() {
-                problem.make_equal(x, y);
-            }
+            restriction.apply(problem);
             restrictions.push(restriction);
         }
 
+        let stats = problem.simplify();
+        log::trace!("Simplification: {} units, {} equivalences, {} clauses removed", stats.units, stats.equivalences, stats.removed_clauses);
+        if problem.is_contradictory() {
+            // The restrictions of this round would give no model, the ones of the previous rounds
+            // are kept
+            log::info!("The restrictions made the problem UNSAT, dropping the last round");
+            problem.backtrack();
+            restrictions.truncate(previous_length);
+            break;
+        }
         println!("Number of active clauses after updated restrictions: {}", problem.number_active_clauses());
 
-        primal_graph = compute_primal_graph(&problem);
-        treewidth = compute_treewidth(primal_graph.clone());
-        log::trace!("Updated treewidth: {}", treewidth);
+        // The decomposition is updated along with the graph, by eliminating again the bags that
+        // the round touched. The regions share the deadline of the round, so that the time given
+        // to the heuristics does not grow with the number of regions.
+        let deadline = heuristics_deadline(args);
+        primal_graph.apply_restrictions(problem, |graph| compute_tree_decomposition(args, problem, graph, deadline));
+        treewidth = match primal_graph.decomposition_width() {
+            Some(width) => width,
+            None => update_decomposition(args, problem, &mut primal_graph),
+        };
+        let treewidth_lower_bound = lower_bound(primal_graph.adjacency());
+        rounds += 1;
+        log::trace!("Updated treewidth: {} (lower bound {}) after {} rounds", treewidth, treewidth_lower_bound, rounds);
+        if treewidth > args.td_threshold {
+            // Both the treewidth and its lower bound must reach the threshold
+            let lower_bound_rounds = expected_rounds(initial_lower_bound, treewidth_lower_bound, args.td_threshold, rounds);
+            let treewidth_rounds = expected_rounds(initial_treewidth, treewidth, args.td_threshold, rounds);
+            let expected = lower_bound_rounds.zip(treewidth_rounds).map(|(lower_bound_rounds, treewidth_rounds)| lower_bound_rounds.max(treewidth_rounds));
+            if expected.is_none_or(|expected| expected > args.td_max_rounds) {
+                log::warn!("The treewidth {} (lower bound {}) is unlikely to reach {} within {} rounds, stopping the contraction", treewidth, treewidth_lower_bound, args.td_threshold, args.td_max_rounds);
+                break;
+            }
+        }
+    }
+    while problem.restriction_level() > start_level {
+        problem.backtrack();
     }
     restrictions
 }
 
-fn insert_in_bucket(buckets: &mut FxHashMap<usize, Vec<usize>>, bucket: usize, element: usize) {
-    let bucket = buckets.entry(bucket).or_default();
-    bucket.push(element);
+/// Writes a tree decomposition of the primal graph of the problem in the file, in PACE .td format.
+/// The vertices are the variables of the input formula, all of them being in the graph.
+pub fn write_tree_decomposition(args: &Args, problem: &Problem, path: &Path) -> io::Result<()> {
+    let mut adjacency = (0..problem.number_input_var()).map(|variable| (variable, FxHashSet::default())).collect::<FxHashMap<usize, FxHashSet<usize>>>();
+    for (&vertex, neighbors) in PrimalGraph::new(problem).adjacency().iter() {
+        adjacency.get_mut(&problem.input_variable(vertex)).unwrap().extend(neighbors.iter().map(|&neighbor| problem.input_variable(neighbor)));
+    }
+    let graph = PrimalGraph::from_adjacency(adjacency);
+    let projection = problem.iter_independent_set().map(|variable| problem.input_variable(variable)).collect::<FxHashSet<usize>>();
+    let decomposition = best_decomposition(&args.td_heuristics, &graph, &projection, heuristics_deadline(args));
+    if let Err(error) = decomposition.check(graph.adjacency()) {
+        log::error!("Invalid tree decomposition of the primal graph: {}", error);
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    decomposition.write_pace(&mut writer, problem.number_input_var())?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::parse_dimacs;
+
+    /// Writes an executable script printing the decomposition after reading the graph
+    fn stub_solver(directory: &Path, name: &str, decomposition: &str) -> String {
+        let script = directory.join(name);
+        std::fs::write(&script, format!("#!/bin/sh\ncat > /dev/null\nprintf '{}'\n", decomposition.replace('\n', "\\n"))).unwrap();
+        let mut permissions = std::fs::metadata(&script).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o755);
+        std::fs::set_permissions(&script, permissions).unwrap();
+        script.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn imported_decompositions_replace_the_heuristic_estimate() {
+        let directory = std::env::temp_dir().join(format!("couaincre-{}-td-import", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        // The primal graph is the cycle 1 2 3 4, of width 2
+        let problem = Problem::from_cnf(parse_dimacs("p cnf 4 4\n1 2 0\n2 3 0\n3 4 0\n-4 -1 0\n".as_bytes()).unwrap(), (0..4).collect(), Count::one(), false);
+        let args = |options: &[&str]| Args::parse_from(["couaincre", "--input", "input.cnf"].iter().chain(options));
+        assert_eq!(update_decomposition(&args(&[]), &problem, &mut PrimalGraph::new(&problem)), 2);
+        // A single bag of width 3 from --td-file
+        let td_file = directory.join("single.td");
+        std::fs::write(&td_file, "s td 1 4 4\nb 1 1 2 3 4\n").unwrap();
+        let with_file = args(&[]);
+        let _ = with_file.td_file_decomposition.set(solver::read_tree_decomposition(&td_file).unwrap());
+        assert_eq!(update_decomposition(&with_file, &problem, &mut PrimalGraph::new(&problem)), 3);
+        // A decomposition missing the edge 4 1 does not fit
+        std::fs::write(&td_file, "s td 2 3 4\nb 1 1 2 3\nb 2 3 4\n1 2\n").unwrap();
+        let invalid_file = args(&[]);
+        let _ = invalid_file.td_file_decomposition.set(solver::read_tree_decomposition(&td_file).unwrap());
+        assert_eq!(update_decomposition(&invalid_file, &problem, &mut PrimalGraph::new(&problem)), 2);
+        // The best of the --td-file and --td-solver decompositions is kept
+        let solver = stub_solver(&directory, "solver.sh", "s td 2 3 4\nb 1 1 2 3\nb 2 1 3 4\n1 2\n");
+        let with_solver = args(&["--td-solver", &solver]);
+        let _ = with_solver.td_file_decomposition.set(TreeDecomposition::read_pace("s td 1 4 4\nb 1 1 2 3 4\n".as_bytes()).unwrap().0);
+        assert_eq!(update_decomposition(&with_solver, &problem, &mut PrimalGraph::new(&problem)), 2);
+        let invalid_solver = stub_solver(&directory, "invalid.sh", "s td 1 2 4\nb 1 1 2\n");
+        let with_invalid_solver = args(&["--td-solver", &invalid_solver]);
+        let _ = with_invalid_solver.td_file_decomposition.set(TreeDecomposition::read_pace("s td 1 4 4\nb 1 1 2 3 4\n".as_bytes()).unwrap().0);
+        assert_eq!(update_decomposition(&with_invalid_solver, &problem, &mut PrimalGraph::new(&problem)), 3);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
 }
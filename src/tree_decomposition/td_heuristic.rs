@@ -1,3 +1,318 @@
 use clap::ValueEnum;
-use rustc_hash::FxHashSet;
+use rustc_hash::{FxHashMap, FxHashSet};
 
+use std::collections::BTreeSet;
+use std::thread;
+use std::time::Instant;
+
+use super::{PrimalGraph, TreeDecomposition};
+
+/// Heuristics computing the elimination order from which a tree decomposition is built
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EliminationHeuristic {
+    /// Eliminates a vertex of minimum degree
+    MinDegree,
+    /// Eliminates a vertex of minimum fill-in, with the scores updated after each elimination
+    MinFill,
+    /// Eliminates a vertex of minimum fill-in, with the scores updated only when they are popped
+    LazyMinFill,
+    /// Eliminates the vertices in the reverse order of a maximum cardinality search
+    Mcs,
+    /// Min-fill in which the fill-in of the projected vertices counts double, so that the other
+    /// vertices tend to be eliminated first
+    WeightedMinFill,
+}
+
+impl std::fmt::Display for EliminationHeuristic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MinDegree => write!(f, "min-degree"),
+            Self::MinFill => write!(f, "min-fill"),
+            Self::LazyMinFill => write!(f, "lazy min-fill"),
+            Self::Mcs => write!(f, "maximum cardinality search"),
+            Self::WeightedMinFill => write!(f, "weighted min-fill"),
+        }
+    }
+}
+
+fn is_over(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Returns the number of missing edges between the neighbors of the node
+pub fn fill_in_score(graph: &FxHashMap<usize, FxHashSet<usize>>, node: usize) -> usize {
+    if graph[&node].is_empty() {
+        return 0;
+    }
+    let number_neighbors = graph[&node].len();
+    let mut missing_edges = (number_neighbors * (number_neighbors - 1)) / 2;
+    let neighbors = graph[&node].iter().copied().collect::<Vec<usize>>();
+    for i in 0..neighbors.len() {
+        for j in (i+1)..neighbors.len() {
+            if graph[&neighbors[i]].contains(&neighbors[j]) {
+                missing_edges -= 1;
+            }
+        }
+    }
+    missing_edges
+}
+
+fn insert_in_bucket(buckets: &mut FxHashMap<usize, Vec<usize>>, bucket: usize, element: usize) {
+    let bucket = buckets.entry(bucket).or_default();
+    bucket.push(element);
+}
+
+/// Returns the order eliminating at each step a vertex of minimum score, starting from the scores
+/// given by `initial_score`. After each elimination, the scores of the neighbors of the eliminated
+/// vertex are updated, and also the ones of their neighbors if `distance_two` is true. Returns
+/// None if the deadline is reached.
+fn greedy_order(graph: &PrimalGraph, distance_two: bool, initial_score: impl Fn(usize) -> usize, score: impl Fn(&FxHashMap<usize, FxHashSet<usize>>, usize) -> usize, deadline: Option<Instant>) -> Option<Vec<usize>> {
+    let mut scores = graph.adjacency().keys().map(|&vertex| (vertex, initial_score(vertex))).collect::<FxHashMap<usize, usize>>();
+    let mut graph = graph.adjacency().clone();
+    let mut queue = scores.iter().map(|(&vertex, &score)| (score, vertex)).collect::<BTreeSet<(usize, usize)>>();
+    let mut order = Vec::with_capacity(graph.len());
+    while let Some((_, vertex)) = queue.pop_first() {
+        if is_over(deadline) {
+            return None;
+        }
+        order.push(vertex);
+        scores.remove(&vertex);
+        let neighbors = graph.remove(&vertex).unwrap_or_default();
+        for &neighbor in neighbors.iter() {
+            let adjacent = graph.get_mut(&neighbor).unwrap();
+            adjacent.remove(&vertex);
+            adjacent.extend(neighbors.iter().copied().filter(|&n| n != neighbor));
+        }
+        let mut affected = neighbors.clone();
+        if distance_two {
+            for neighbor in neighbors.iter() {
+                affected.extend(graph[neighbor].iter().copied());
+            }
+        }
+        for vertex in affected {
+            let new_score = score(&graph, vertex);
+            let old_score = scores.insert(vertex, new_score).unwrap();
+            if old_score != new_score {
+                queue.remove(&(old_score, vertex));
+                queue.insert((new_score, vertex));
+            }
+        }
+    }
+    Some(order)
+}
+
+/// Returns a min-fill elimination order of the graph, or None if the deadline is reached
+fn lazy_min_fill_order(primal_graph: &PrimalGraph, deadline: Option<Instant>) -> Option<Vec<usize>> {
+    let mut graph = primal_graph.adjacency().clone();
+    let number_var = graph.len();
+
+    // Buckets used to compute the order. We place each node in a bucket corresponding to its
+    // heuristic score. Then, we can process nodes in increasing order of bucket.
+    let mut buckets = FxHashMap::<usize, Vec<usize>>::default();
+    // Flag to indicate if the score of a node must be recomputed. We lazily recompute them
+    // when poping them from the bucket as this computation can be long (e.g., min-fill)
+    //
+    // /!\ Note: since we lazily recompute the score when popping nodes from the bucket, we
+    // might process nodes in non-greedy order (i.e., process a node that has a worst-score
+    // than another non-processed node). However, re-computing the heuristic at each
+    // modification of the primal graph is not scalable for large graphs.
+    //
+    let mut recompute_score = FxHashSet::<usize>::default();
+    // Current minimum score. Since we greedily select nodes based on their minimum score, this
+    // give the next bucket to select a node from.
+    let mut min_score = usize::MAX;
+    // Initialise the buckets
+    for (candidate, _) in graph.iter() {
+        let score = primal_graph.fill_in(*candidate);
+        min_score = min_score.min(score);
+        insert_in_bucket(&mut buckets, score, *candidate);
+    }
+    // We compute the order for each node.
+    let mut order = Vec::with_capacity(number_var);
+    while order.len() != number_var {
+        if is_over(deadline) {
+            return None;
+        }
+        // Finds the next non-empty bucket
+        while !buckets.contains_key(&min_score) || buckets.get(&min_score).unwrap().is_empty() {
+            min_score += 1;
+        }
+        // Pop a node from the bucket and recompute its score if needed. If the new score is
+        // worst than the computed one, put it in the associated bucket.
+        let node = buckets.get_mut(&min_score).unwrap().pop().unwrap();
+        if recompute_score.contains(&node) {
+            recompute_score.remove(&node);
+            let new_score = fill_in_score(&graph, node);
+            if new_score > min_score {
+                insert_in_bucket(&mut buckets, new_score, node);
+                continue;
+            }
+            min_score = min_score.min(new_score);
+        }
+        order.push(node);
+
+        // Apply the node elimination
+
+        // Remove node from the graph (disconnect it from its neighbors) and connect all of its
+        // neighbors
+        let neighbors = graph[&node].iter().copied().collect::<Vec<usize>>();
+        for neighbor in neighbors.iter().copied() {
+            graph.get_mut(&neighbor).unwrap().extend(neighbors.iter().copied().filter(|n| *n != neighbor));
+            graph.get_mut(&neighbor).unwrap().remove(&node);
+        }
+        // Flags node for which the heuristic needs to be recomputed
+        // All nodes at a distance of 2 in the graph can have their min-fill heuristic
+        // changed.
+        for neighbor in graph[&node].iter().copied() {
+            recompute_score.insert(neighbor);
+            for neighbor_of_neighbor in graph[&neighbor].iter().copied().filter(|n| *n != node) {
+                recompute_score.insert(neighbor_of_neighbor);
+            }
+        }
+        graph.get_mut(&node).unwrap().clear();
+    }
+    Some(order)
+}
+
+/// Returns the reverse of the order in which a maximum cardinality search visits the graph: each
+/// visited vertex is one with the most visited neighbors. Returns None if the deadline is reached.
+fn maximum_cardinality_search_order(graph: &FxHashMap<usize, FxHashSet<usize>>, deadline: Option<Instant>) -> Option<Vec<usize>> {
+    let mut vertices = graph.keys().copied().collect::<Vec<usize>>();
+    vertices.sort_unstable_by(|a, b| b.cmp(a));
+    let mut visited_neighbors = graph.keys().map(|&vertex| (vertex, 0)).collect::<FxHashMap<usize, usize>>();
+    // Vertices by number of visited neighbors. A vertex stays in the buckets of its former
+    // numbers, where it is skipped.
+    let mut buckets = vec![vertices];
+    let mut max_bucket = 0;
+    let mut visited = FxHashSet::<usize>::default();
+    let mut order = Vec::with_capacity(graph.len());
+    while order.len() != graph.len() {
+        if is_over(deadline) {
+            return None;
+        }
+        let Some(vertex) = buckets[max_bucket].pop() else {
+            max_bucket -= 1;
+            continue;
+        };
+        if visited.contains(&vertex) || visited_neighbors[&vertex] != max_bucket {
+            continue;
+        }
+        visited.insert(vertex);
+        order.push(vertex);
+        for &neighbor in graph[&vertex].iter().filter(|neighbor| !visited.contains(neighbor)) {
+            let count = visited_neighbors.get_mut(&neighbor).unwrap();
+            *count += 1;
+            if buckets.len() <= *count {
+                buckets.push(vec![]);
+            }
+            buckets[*count].push(neighbor);
+            max_bucket = max_bucket.max(*count);
+        }
+    }
+    order.reverse();
+    Some(order)
+}
+
+impl EliminationHeuristic {
+
+    /// Computes the elimination order of the graph, in which `projection` are the projected
+    /// vertices. Returns None if the deadline is reached.
+    pub fn elimination_order(&self, graph: &PrimalGraph, projection: &FxHashSet<usize>, deadline: Option<Instant>) -> Option<Vec<usize>> {
+        let weighted = |vertex: usize, fill_in: usize| if projection.contains(&vertex) { 2 * fill_in } else { fill_in };
+        match self {
+            Self::MinDegree => greedy_order(graph, false, |vertex| graph.adjacency()[&vertex].len(), |graph, vertex| graph[&vertex].len(), deadline),
+            Self::MinFill => greedy_order(graph, true, |vertex| graph.fill_in(vertex), fill_in_score, deadline),
+            Self::LazyMinFill => lazy_min_fill_order(graph, deadline),
+            Self::Mcs => maximum_cardinality_search_order(graph.adjacency(), deadline),
+            Self::WeightedMinFill => greedy_order(graph, true, |vertex| weighted(vertex, graph.fill_in(vertex)), |graph, vertex| weighted(vertex, fill_in_score(graph, vertex)), deadline),
+        }
+    }
+}
+
+/// Computes a tree decomposition of the graph with each heuristic and returns the one of smallest
+/// width. The first heuristic (lazy min-fill if there is none) always completes, while the other
+/// ones run in parallel and are abandoned if they do not finish before the deadline.
+pub fn best_decomposition(heuristics: &[EliminationHeuristic], graph: &PrimalGraph, projection: &FxHashSet<usize>, deadline: Instant) -> TreeDecomposition {
+    let first = heuristics.first().copied().unwrap_or(EliminationHeuristic::LazyMinFill);
+    thread::scope(|scope| {
+        let others = heuristics.iter().skip(1).map(|&heuristic| {
+            scope.spawn(move || {
+                let order = heuristic.elimination_order(graph, projection, Some(deadline))?;
+                Some(TreeDecomposition::from_elimination_order(graph.adjacency(), &order))
+            })
+        }).collect::<Vec<_>>();
+        let order = first.elimination_order(graph, projection, None).unwrap();
+        let mut best = TreeDecomposition::from_elimination_order(graph.adjacency(), &order);
+        log::trace!("Width {} with the {} heuristic", best.width(), first);
+        for (heuristic, handle) in heuristics.iter().skip(1).zip(others) {
+            match handle.join().unwrap() {
+                Some(decomposition) => {
+                    log::trace!("Width {} with the {} heuristic", decomposition.width(), heuristic);
+                    if decomposition.width() < best.width() {
+                        best = decomposition;
+                    }
+                },
+                None => log::trace!("The {} heuristic did not finish before the deadline", heuristic),
+            }
+        }
+        best
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::count::Count;
+    use crate::parser::parse_dimacs;
+    use crate::problem::Problem;
+
+    /// Returns the primal graph of a formula with a binary clause for each edge
+    fn graph(number_vertices: usize, edges: impl IntoIterator<Item = (usize, usize)>) -> PrimalGraph {
+        let clauses = edges.into_iter().map(|(a, b)| format!("{} {} 0\n", a + 1, b + 1)).collect::<Vec<String>>();
+        let dimacs = format!("p cnf {} {}\n{}", number_vertices, clauses.len(), clauses.concat());
+        PrimalGraph::new(&Problem::from_cnf(parse_dimacs(dimacs.as_bytes()).unwrap(), (0..number_vertices).collect(), Count::one(), false))
+    }
+
+    #[test]
+    fn heuristics_find_the_width_of_chordal_graphs_and_cycles() {
+        // A binary tree, a cycle, a clique and two triangles sharing an edge, with their treewidth
+        let graphs = [
+            (graph(15, (1..15).map(|v| ((v - 1) / 2, v))), 1),
+            (graph(9, (0..9).map(|v| (v, (v + 1) % 9))), 2),
+            (graph(6, (0..6).flat_map(|u| (u + 1..6).map(move |v| (u, v)))), 5),
+            (graph(4, [(0, 1), (1, 2), (2, 0), (1, 3), (2, 3)]), 2),
+        ];
+        let heuristics = EliminationHeuristic::value_variants();
+        for (graph, width) in graphs.iter() {
+            let number_vertices = graph.adjacency().len();
+            let projection = (0..number_vertices).step_by(2).collect::<FxHashSet<usize>>();
+            for heuristic in heuristics {
+                let mut order = heuristic.elimination_order(graph, &projection, None).unwrap();
+                let decomposition = TreeDecomposition::from_elimination_order(graph.adjacency(), &order);
+                assert_eq!(decomposition.check(graph.adjacency()), Ok(()), "{}", heuristic);
+                // Lazy min-fill may eliminate a vertex whose score is outdated, so it is not exact
+                // on these graphs
+                if !matches!(heuristic, EliminationHeuristic::LazyMinFill) {
+                    assert_eq!(decomposition.width(), *width, "{}", heuristic);
+                }
+                order.sort_unstable();
+                assert_eq!(order, (0..number_vertices).collect::<Vec<usize>>(), "{}", heuristic);
+            }
+            assert_eq!(best_decomposition(heuristics, graph, &projection, Instant::now() + Duration::from_secs(10)).width(), *width);
+        }
+    }
+
+    #[test]
+    fn heuristics_stop_at_the_deadline() {
+        let grid = graph(100, (0..100).flat_map(|v| [(v % 10 != 9).then_some((v, v + 1)), (v < 90).then_some((v, v + 10))]).flatten());
+        let projection = FxHashSet::default();
+        for heuristic in EliminationHeuristic::value_variants() {
+            assert!(heuristic.elimination_order(&grid, &projection, Some(Instant::now())).is_none(), "{}", heuristic);
+        }
+        // The first heuristic completes even if the other ones are abandoned
+        let decomposition = best_decomposition(&[EliminationHeuristic::MinDegree, EliminationHeuristic::MinFill], &grid, &projection, Instant::now());
+        assert_eq!(decomposition.check(grid.adjacency()), Ok(()));
+    }
+}
@@ -1,43 +1,41 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio, exit};
-
-use crate::Args;
-use crate::counter::Counter;
-
-/// Returns the number of variables and clauses in the input file
-pub fn metadata_from_header(args: &Args) -> (usize, usize) {
-    let file = File::open(args.input.clone()).unwrap();
-    let reader = BufReader::new(file);
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if line.starts_with("p cnf") {
-            let mut split = line.split_whitespace().skip(2);
-            let number_var = split.next().unwrap().parse::<usize>().unwrap();
-            let number_clauses = split.next().unwrap().parse::<usize>().unwrap();
-            return (number_var, number_clauses);
-        }
+use malachite::Natural;
+use malachite::base::num::basic::traits::Zero;
+use malachite::base::num::conversion::traits::SciMantissaAndExponent;
+
+
+/// Returns the log10 of the natural number, -inf for 0
+pub fn log10_natural(n: &Natural) -> f64 {
+    if *n == Natural::ZERO {
+        return f64::NEG_INFINITY;
     }
-    log::error!("No header found in DIMACS file when looking for metadata");
-    panic!();
+    let (mantissa, exponent): (f64, u64) = n.sci_mantissa_and_exponent();
+    mantissa.log10() + (exponent as f64) * std::f64::consts::LOG10_2
 }
 
-fn check_cmd(cmd: &str, args: &[&str]) {
-    if let Err(_) = Command::new(cmd)
-        .args(args)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status() {
-        log::error!("Command {} not found", cmd);
-        exit(1);
+/// 128-bit FNV-1a hash, stable across runs and platforms, used to key the caches
+pub struct ContentHash(u128);
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        Self(0x6c62272e07bb014262b821756295c58d)
     }
 }
 
-pub fn check_executables(args: &Args) {
-    check_cmd("cadical", &["--help"]);
-    check_cmd("bpe", &["--help"]);
-    match args.counter {
-        Counter::D4 => check_cmd("d4", &["<<<", "p cnf 1 1 1 0"]),
-        Counter::Ganak => check_cmd("ganak", &["--help"]),
+impl ContentHash {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u128;
+            self.0 = self.0.wrapping_mul(0x0000000001000000000000000000013b);
+        }
+    }
+
+    /// Returns the hash as an hexadecimal string
+    pub fn finish(&self) -> String {
+        format!("{:032x}", self.0)
     }
 }